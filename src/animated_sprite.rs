@@ -1,18 +1,30 @@
-use sdl2::rect::Rect;
-use sdl2::render::WindowCanvas;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{Canvas, RenderTarget};
 use sdl2::render::TextureCreator;
-use sdl2::video::WindowContext;
 use sdl2::pixels::Color;
 use sdl2::image::LoadSurface;
+use crate::animation::{AnimationController, Clip};
 use crate::common::Size;
+use crate::sprite_sheet::SheetInfo;
 
 pub struct AnimatedSprite<'a> {
     sprite_sheet: Option<sdl2::render::Texture<'a>>,
     frame_width: usize,
     frame_height: usize,
     num_frames: (usize, usize),
+    // frames actually on the sheet; the last strip can be short
+    frame_count: usize,
+    // empty pixels to the right of and below every frame
+    padding: usize,
+    // the layout and clips from the sheet's sidecar file, which win over the settings below
+    sheet_info: Option<SheetInfo>,
+    // frame size to use instead of dividing the sheet into `num_frames`; the frame count is then
+    // however many whole frames fit, so sheets with ragged edges still line up
+    fixed_frame_size: Option<(usize, usize)>,
     custom_frame_sequence: Option<Vec<(usize, usize)>>,
     frame_sequence_length: usize,
+    // kept so that loading the sheet again keeps its tint
+    color_mod: Option<Color>,
 }
 
 impl<'a> AnimatedSprite<'a> {
@@ -22,13 +34,27 @@ impl<'a> AnimatedSprite<'a> {
             frame_width: 0,
             frame_height: 0,
             num_frames: (0, 0),
+            frame_count: 0,
+            padding: 0,
+            sheet_info: None,
+            fixed_frame_size: None,
             custom_frame_sequence: None,
             frame_sequence_length: 0,
+            color_mod: None,
         }
     }
+    #[allow(dead_code)]
     pub fn set_num_frames(&mut self, n: (usize, usize)) {
         self.num_frames = n;
     }
+    pub fn set_fixed_frame_size(&mut self, size: Option<(usize, usize)>) {
+        self.fixed_frame_size = size;
+    }
+    // takes effect on the next load; loading from a file with a sidecar replaces it
+    pub fn set_sheet_info(&mut self, info: Option<SheetInfo>) {
+        self.sheet_info = info;
+    }
+    #[allow(dead_code)]
     pub fn set_custom_frame_sequence(&mut self, seq: Option<Vec<(usize, usize)>>) {
         if let Some(s) = &seq {
             self.frame_sequence_length = s.len();
@@ -43,47 +69,149 @@ impl<'a> AnimatedSprite<'a> {
     pub fn frame_height(&self) -> usize {
         self.frame_height
     }
+    #[allow(dead_code)]
     pub fn num_frames(&self) -> (usize, usize) {
         self.num_frames
     }
-    pub fn load_from_file(&mut self, texture_creator: &'a TextureCreator<WindowContext>,
-                          path: &str, color_key: Option<Color>) -> Result<(), String> {
-        if self.num_frames.0 == 0 || self.num_frames.1 == 0 {
-            panic!("ERROR: Cannot load to an AnimatedSprite with {} horizontal frames and \
-                    {} vertical frames", self.num_frames.0, self.num_frames.1);
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+    // whether the sheet has a frame at (column, row)
+    pub fn has_cell(&self, cell: (usize, usize)) -> bool {
+        cell.0 < self.num_frames.0 && cell.1 < self.num_frames.1 && cell.0 * self.num_frames.1 + cell.1 < self.frame_count
+    }
+    // the pivot of a frame from its top left, in pixels; the middle of its bottom unless the sheet
+    // says otherwise
+    pub fn origin(&self) -> (i32, i32) {
+        self.sheet_info.as_ref().and_then(|info| info.origin)
+            .unwrap_or((self.frame_width as i32 / 2, self.frame_height as i32))
+    }
+    // the named clips from the sheet's sidecar file
+    pub fn clips(&self) -> impl Iterator<Item=(&String, &Clip)> {
+        self.sheet_info.iter().flat_map(|info| info.clips.iter())
+    }
+    // a controller holding every clip of the sheet, with none playing yet
+    pub fn animation_controller(&self) -> AnimationController {
+        let mut controller = AnimationController::new();
+        for (name, clip) in self.clips() {
+            controller.add_clip(name, clip.clone());
         }
-        self.sprite_sheet = None;
-        let mut surface = sdl2::surface::Surface::from_file(path).unwrap();
+        controller
+    }
+    // reads the sheet's sidecar file too, if it has one; a sheet without one needs a frame size or
+    // frame count set first
+    pub fn load_from_file<C>(&mut self, texture_creator: &'a TextureCreator<C>,
+                          path: &str, color_key: Option<Color>) -> Result<(), String> {
+        let info = SheetInfo::load_for(path)?;
+        let mut surface = sdl2::surface::Surface::from_file(path)?;
         if let Some(key) = color_key {
-            if let Err(msg) = surface.set_color_key(true, key) {
-                return Err(msg);
+            surface.set_color_key(true, key)?;
+        }
+        if info.is_some() {
+            self.sheet_info = info;
+        }
+        self.load_from_surface(texture_creator, surface)
+    }
+    // for pixels made at runtime rather than read from a file
+    pub fn load_from_surface<C>(&mut self, texture_creator: &'a TextureCreator<C>,
+                                surface: sdl2::surface::Surface) -> Result<(), String> {
+        let (sheet_w, sheet_h) = (surface.width() as usize, surface.height() as usize);
+        match (&self.sheet_info, self.fixed_frame_size) {
+            (Some(info), _) => {
+                let rows = info.rows();
+                let slot_w = info.frame_size.0.map_or(sheet_w / info.columns, |w| w + info.padding);
+                let slot_h = info.frame_size.1.map_or(sheet_h / rows, |h| h + info.padding);
+                if slot_w <= info.padding || slot_h <= info.padding {
+                    return Err(format!("A {}x{} sheet is too small for {} frames", sheet_w, sheet_h, info.frames));
+                }
+                self.frame_width = slot_w - info.padding;
+                self.frame_height = slot_h - info.padding;
+                self.padding = info.padding;
+                self.num_frames = (info.columns, rows);
+                self.frame_count = info.frames;
             }
+            (None, Some((w, h))) if w > 0 && h > 0 => {
+                self.frame_width = w;
+                self.frame_height = h;
+                self.padding = 0;
+                self.num_frames = (sheet_w / w, sheet_h / h);
+                self.frame_count = self.num_frames.0 * self.num_frames.1;
+            }
+            (None, None) if self.num_frames.0 > 0 && self.num_frames.1 > 0 => {
+                self.frame_width = sheet_w / self.num_frames.0;
+                self.frame_height = sheet_h / self.num_frames.1;
+                self.padding = 0;
+                self.frame_count = self.num_frames.0 * self.num_frames.1;
+            }
+            _ => return Err("A sheet without a sidecar file needs a frame size or frame count".to_string()),
+        }
+        self.sprite_sheet = Some(texture_creator.create_texture_from_surface(surface).map_err(|e| e.to_string())?);
+        if let Some(color) = self.color_mod {
+            self.set_color_mod(color);
         }
-        self.frame_width = surface.width() as usize / self.num_frames.0;
-        self.frame_height = surface.height() as usize / self.num_frames.1;
-        self.sprite_sheet = Some(texture_creator.create_texture_from_surface(surface).unwrap());
         Ok(())
     }
-    pub fn render(&self, canvas: &mut WindowCanvas, x: i32, y: i32, size: Option<Size>, frame: (usize, usize)) -> Result<(), String> {
-        let mut render_quad = Rect::new(x, y, self.frame_width as u32, self.frame_height as u32);
+    pub fn set_color_mod(&mut self, color: Color) {
+        self.color_mod = Some(color);
+        if let Some(t) = &mut self.sprite_sheet {
+            t.set_color_mod(color.r, color.g, color.b);
+        }
+    }
+    #[allow(dead_code)]
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, x: i32, y: i32, size: Option<Size>, frame: (usize, usize)) -> Result<(), String> {
+        self.render_flipped(canvas, x, y, size, frame, false)
+    }
+    // `render`, mirrored left to right when `flip_horizontal` is set; with a custom frame sequence,
+    // `frame.0` is the position in it
+    pub fn render_flipped<T: RenderTarget>(&self, canvas: &mut Canvas<T>, x: i32, y: i32, size: Option<Size>,
+                                           frame: (usize, usize), flip_horizontal: bool) -> Result<(), String> {
+        let cell = match &self.custom_frame_sequence {
+            Some(cfs) => *cfs.get(frame.0).ok_or_else(|| {
+                format!("Frame {} is past the end of a sequence of {}", frame.0, self.frame_sequence_length)
+            })?,
+            None => frame,
+        };
+        self.render_cell(canvas, Point::new(x, y), size, cell, None, flip_horizontal)
+    }
+    // draws the frame at (column, row) of the sheet with its origin at `at`, turned `angle` degrees
+    // clockwise about it; mirroring mirrors the origin too
+    pub fn render_rotated<T: RenderTarget>(&self, canvas: &mut Canvas<T>, at: Point, size: Option<Size>,
+                                           cell: (usize, usize), angle: f64, flip_horizontal: bool) -> Result<(), String> {
+        let (w, h) = size.map_or((self.frame_width as f64, self.frame_height as f64), |s| (f64::from(s.w), f64::from(s.h)));
+        let (ox, oy) = self.origin();
+        let ox = if flip_horizontal { self.frame_width as i32 - ox } else { ox };
+        let sx = w / self.frame_width.max(1) as f64;
+        let sy = h / self.frame_height.max(1) as f64;
+        let center = Point::new((f64::from(ox) * sx).round() as i32, (f64::from(oy) * sy).round() as i32);
+        self.render_cell(canvas, at - center, size, cell, Some((angle, center)), flip_horizontal)
+    }
+    // `render_rotated` for the frame `controller` is on, or the first frame while it has no clip
+    // playing
+    pub fn render_animation<T: RenderTarget>(&self, canvas: &mut Canvas<T>, at: Point, size: Option<Size>,
+                                             controller: &AnimationController, angle: f64, flip_horizontal: bool) -> Result<(), String> {
+        let cell = controller.frame().unwrap_or((0, 0));
+        self.render_rotated(canvas, at, size, cell, angle, flip_horizontal)
+    }
+    // draws the frame at (column, row) of the sheet with its top left at `dest`, optionally turned by
+    // an angle in degrees about a point of the frame
+    fn render_cell<T: RenderTarget>(&self, canvas: &mut Canvas<T>, dest: Point, size: Option<Size>, cell: (usize, usize),
+                                    rotation: Option<(f64, Point)>, flip_horizontal: bool) -> Result<(), String> {
+        let sheet = match &self.sprite_sheet {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        if !self.has_cell(cell) {
+            return Err(format!("Frame {:?} is not on a sheet of {} frames", cell, self.frame_count));
+        }
+        let mut render_quad = Rect::new(dest.x(), dest.y(), self.frame_width as u32, self.frame_height as u32);
         if let Some(s) = size {
             render_quad.set_width(s.w);
             render_quad.set_height(s.h);
         }
-        let frame_clip: Rect = match &self.custom_frame_sequence {
-            Some(cfs) => {
-                let f: &(usize, usize) = cfs.get(frame.0).unwrap();
-                Rect::new((f.0 * self.frame_width) as i32, (f.1 * self.frame_height) as i32,
-                          self.frame_width as u32, self.frame_height as u32)
-            }
-            None => Rect::new((frame.0 * self.frame_width) as i32, (frame.1 * self.frame_height) as i32,
-                              self.frame_width as u32, self.frame_height as u32)
-        };
-        if let Some(ss) = &self.sprite_sheet {
-            if let Err(msg) = canvas.copy(&ss, frame_clip, render_quad) {
-                return Err(msg);
-            }
-        }
-        Ok(())
+        let (slot_w, slot_h) = (self.frame_width + self.padding, self.frame_height + self.padding);
+        let frame_clip = Rect::new((cell.0 * slot_w) as i32, (cell.1 * slot_h) as i32,
+                                   self.frame_width as u32, self.frame_height as u32);
+        let (angle, center) = rotation.map_or((0.0, None), |(angle, center)| (angle, Some(center)));
+        canvas.copy_ex(sheet, frame_clip, render_quad, angle, center, flip_horizontal, false)
     }
 }
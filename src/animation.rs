@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+// frames shorter than this are stretched to it, so a clip always moves forward
+const MIN_FRAME_TIME: f64 = 0.001;
+
+// what a clip does once it reaches its last frame
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub enum PlayMode {
+    // starts over from the first frame
+    Loop,
+    // plays backwards to the first frame, then forwards again
+    PingPong,
+    // stays on the last frame
+    Once,
+}
+
+// named frames of a sheet, each shown for its own time
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Clip {
+    // (column, row) of each frame in the sheet
+    frames: Vec<(usize, usize)>,
+    // in seconds, one per frame
+    durations: Vec<f64>,
+    mode: PlayMode,
+}
+
+impl Clip {
+    pub fn new(mode: PlayMode) -> Clip {
+        Clip {
+            frames: Vec::new(),
+            durations: Vec::new(),
+            mode,
+        }
+    }
+    pub fn add_frame(&mut self, frame: (usize, usize), duration: f64) {
+        self.frames.push(frame);
+        self.durations.push(duration.max(MIN_FRAME_TIME));
+    }
+    pub fn frames(&self) -> &[(usize, usize)] {
+        &self.frames
+    }
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+// what happened to the playing clip during an update
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub enum AnimationEvent {
+    // a looping or ping-pong clip came back to its first frame
+    Looped(String),
+    // a play-once clip reached the end of its last frame
+    Finished(String),
+}
+
+// plays one of a set of clips at a time, advanced by elapsed time; `AnimatedSprite::render_animation`
+// draws whichever frame it is on
+pub struct AnimationController {
+    clips: HashMap<String, Clip>,
+    current: Option<String>,
+    // the frame of the current clip, and how long it has been shown
+    index: usize,
+    elapsed: f64,
+    // ping-pong clips step backwards on the way back
+    reversed: bool,
+    finished: bool,
+    speed: f64,
+}
+
+impl AnimationController {
+    pub fn new() -> AnimationController {
+        AnimationController {
+            clips: HashMap::new(),
+            current: None,
+            index: 0,
+            elapsed: 0.0,
+            reversed: false,
+            finished: false,
+            speed: 1.0,
+        }
+    }
+    // replaces any clip of the same name; the clip playing under it starts over
+    pub fn add_clip(&mut self, name: &str, clip: Clip) {
+        self.clips.insert(name.to_string(), clip);
+        if self.current.as_deref() == Some(name) {
+            self.restart();
+        }
+    }
+    // switches to the clip called `name` from its first frame; does nothing if it is already playing,
+    // so it can be called every tick
+    pub fn play(&mut self, name: &str) -> Result<(), String> {
+        if self.current.as_deref() == Some(name) {
+            return Ok(());
+        }
+        if !self.clips.contains_key(name) {
+            return Err(format!("There is no animation clip called {}", name));
+        }
+        self.current = Some(name.to_string());
+        self.restart();
+        Ok(())
+    }
+    // the current clip from its first frame
+    pub fn restart(&mut self) {
+        self.index = 0;
+        self.elapsed = 0.0;
+        self.reversed = false;
+        self.finished = false;
+    }
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed.max(0.0);
+    }
+    // advances the current clip by `dt` seconds, times the speed; returns what happened on the way,
+    // at most once each, since a long `dt` can go round a short clip many times
+    pub fn update(&mut self, dt: f64) -> Vec<AnimationEvent> {
+        let mut events = Vec::new();
+        let clips = &self.clips;
+        let (name, clip) = match self.current.as_ref().and_then(|n| clips.get(n).map(|c| (n, c))) {
+            Some(c) => c,
+            None => return events,
+        };
+        if clip.is_empty() || self.finished {
+            return events;
+        }
+        self.elapsed += dt * self.speed;
+        let last = clip.len() - 1;
+        while self.elapsed >= clip.durations[self.index] {
+            self.elapsed -= clip.durations[self.index];
+            let looped = match clip.mode {
+                PlayMode::Loop if self.index == last => {
+                    self.index = 0;
+                    true
+                }
+                PlayMode::PingPong if last == 0 => true,
+                PlayMode::PingPong if self.reversed || self.index == last => {
+                    self.index -= 1;
+                    self.reversed = self.index > 0;
+                    self.index == 0
+                }
+                PlayMode::Once if self.index == last => {
+                    self.finished = true;
+                    self.elapsed = 0.0;
+                    events.push(AnimationEvent::Finished(name.clone()));
+                    break;
+                }
+                _ => {
+                    self.index += 1;
+                    false
+                }
+            };
+            if looped && !events.iter().any(|e| matches!(e, AnimationEvent::Looped(_))) {
+                events.push(AnimationEvent::Looped(name.clone()));
+            }
+        }
+        events
+    }
+    // the (column, row) in the sheet of the frame to draw, if a clip with frames is playing
+    pub fn frame(&self) -> Option<(usize, usize)> {
+        let clip = self.clips.get(self.current.as_ref()?)?;
+        clip.frames.get(self.index).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a controller playing a clip called "clip" of frames (0, 0), (0, 1) and so on, a second each
+    fn playing(mode: PlayMode, frames: usize) -> AnimationController {
+        let mut clip = Clip::new(mode);
+        for row in 0..frames {
+            clip.add_frame((0, row), 1.0);
+        }
+        let mut controller = AnimationController::new();
+        controller.add_clip("clip", clip);
+        controller.play("clip").unwrap();
+        controller
+    }
+
+    // the row shown after each of `steps` updates of a second, and the events of every update
+    fn run(controller: &mut AnimationController, steps: usize) -> Vec<(usize, Vec<AnimationEvent>)> {
+        (0..steps).map(|_| {
+            let events = controller.update(1.0);
+            (controller.frame().unwrap().1, events)
+        }).collect()
+    }
+
+    fn looped() -> Vec<AnimationEvent> {
+        vec![AnimationEvent::Looped("clip".to_string())]
+    }
+
+    fn finished() -> Vec<AnimationEvent> {
+        vec![AnimationEvent::Finished("clip".to_string())]
+    }
+
+    #[test]
+    fn loops_start_over_after_the_last_frame() {
+        let mut controller = playing(PlayMode::Loop, 3);
+        assert_eq!(controller.frame(), Some((0, 0)));
+        assert_eq!(run(&mut controller, 4), vec![(1, vec![]), (2, vec![]), (0, looped()), (1, vec![])]);
+    }
+
+    #[test]
+    fn ping_pong_turns_around_at_both_ends() {
+        let mut controller = playing(PlayMode::PingPong, 3);
+        assert_eq!(run(&mut controller, 6),
+                   vec![(1, vec![]), (2, vec![]), (1, vec![]), (0, looped()), (1, vec![]), (2, vec![])]);
+    }
+
+    #[test]
+    fn ping_pong_of_two_frames_alternates() {
+        let mut controller = playing(PlayMode::PingPong, 2);
+        assert_eq!(run(&mut controller, 4), vec![(1, vec![]), (0, looped()), (1, vec![]), (0, looped())]);
+    }
+
+    #[test]
+    fn ping_pong_of_one_frame_loops_in_place() {
+        let mut controller = playing(PlayMode::PingPong, 1);
+        assert_eq!(run(&mut controller, 2), vec![(0, looped()), (0, looped())]);
+    }
+
+    #[test]
+    fn once_stops_on_the_last_frame() {
+        let mut controller = playing(PlayMode::Once, 3);
+        assert_eq!(run(&mut controller, 5), vec![(1, vec![]), (2, vec![]), (2, finished()), (2, vec![]), (2, vec![])]);
+        controller.restart();
+        assert_eq!(controller.frame(), Some((0, 0)));
+        assert_eq!(run(&mut controller, 1), vec![(1, vec![])]);
+    }
+
+    #[test]
+    fn a_long_update_reports_each_event_once() {
+        let mut controller = playing(PlayMode::Loop, 3);
+        assert_eq!(controller.update(10.0), looped());
+        assert_eq!(controller.frame(), Some((0, 1)));
+        let mut controller = playing(PlayMode::Once, 3);
+        assert_eq!(controller.update(10.0), finished());
+        assert_eq!(controller.frame(), Some((0, 2)));
+    }
+
+    #[test]
+    fn speed_scales_time_and_play_keeps_the_current_clip_going() {
+        let mut controller = playing(PlayMode::Loop, 3);
+        controller.set_speed(2.0);
+        controller.update(1.0);
+        assert_eq!(controller.frame(), Some((0, 2)));
+        controller.play("clip").unwrap();
+        assert_eq!(controller.frame(), Some((0, 2)));
+        assert!(controller.play("missing").is_err());
+    }
+}
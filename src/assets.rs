@@ -0,0 +1,225 @@
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::TextureCreator;
+use sdl2::surface::Surface;
+use crate::animated_sprite::AnimatedSprite;
+use crate::atlas::Atlas;
+use crate::sprite::{SharedTexture, Sprite};
+use crate::sprite_sheet::SheetInfo;
+
+// missing images are drawn as a checkerboard of this and black, which is hard to overlook
+pub const PLACEHOLDER_COLOR: Color = Color { r: 255, g: 0, b: 255, a: 255 };
+const PLACEHOLDER_SIZE: u32 = 16;
+const PLACEHOLDER_SQUARE: u32 = 4;
+// how often watched images are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+// a loaded asset, shared by everything that asked for the same file; cloning one is cheap, and a
+// reload shows through every clone
+pub struct Handle<T>(Rc<RefCell<T>>);
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Handle<T> {
+        Handle(self.0.clone())
+    }
+}
+
+impl<T> Handle<T> {
+    fn new(asset: T) -> Handle<T> {
+        Handle(Rc::new(RefCell::new(asset)))
+    }
+    pub fn get(&self) -> Ref<'_, T> {
+        self.0.borrow()
+    }
+    pub fn get_mut(&self) -> RefMut<'_, T> {
+        self.0.borrow_mut()
+    }
+}
+
+pub type SpriteHandle<'a> = Handle<Sprite<'a>>;
+pub type AnimationHandle<'a> = Handle<AnimatedSprite<'a>>;
+
+// a sheet's path, fallback frame size and color key
+type AnimationKey = (String, Option<(usize, usize)>, Option<Color>);
+
+struct Entry<T> {
+    handle: Handle<T>,
+    // when the file was last changed as of loading it; None if it did not exist
+    modified: Option<SystemTime>,
+}
+
+// loads every image once and hands out handles to it; files that cannot be loaded are reported
+// once and stand in as placeholders, and in watch mode changed images are loaded again while the
+// game runs. the data files are not assets: each registry reads its own file once at startup
+pub struct Assets<'a, C> {
+    texture_creator: &'a TextureCreator<C>,
+    // packed sprites, drawn from instead of their own files until those change
+    atlas: Option<Atlas<'a>>,
+    placeholder: SharedTexture<'a>,
+    sprites: HashMap<(String, Option<Color>), Entry<Sprite<'a>>>,
+    animations: HashMap<AnimationKey, Entry<AnimatedSprite<'a>>>,
+    // paths already reported as missing
+    missing: BTreeSet<String>,
+    watching: bool,
+    last_check: Instant,
+}
+
+impl<'a, C> Assets<'a, C> {
+    pub fn new(texture_creator: &'a TextureCreator<C>, atlas: Option<Atlas<'a>>) -> Result<Assets<'a, C>, String> {
+        let surface = placeholder_surface(PLACEHOLDER_SIZE, PLACEHOLDER_SIZE)?;
+        let texture = texture_creator.create_texture_from_surface(surface).map_err(|e| e.to_string())?;
+        Ok(Assets {
+            texture_creator,
+            atlas,
+            placeholder: Rc::new(RefCell::new(texture)),
+            sprites: HashMap::new(),
+            animations: HashMap::new(),
+            missing: BTreeSet::new(),
+            watching: false,
+            last_check: Instant::now(),
+        })
+    }
+    // the image at `path`; pixels of `color_key` are transparent
+    pub fn sprite(&mut self, path: &str, color_key: Option<Color>) -> SpriteHandle<'a> {
+        let key = (path.to_string(), color_key);
+        if let Some(entry) = self.sprites.get(&key) {
+            return entry.handle.clone();
+        }
+        let modified = modified(path);
+        let packed = self.atlas.as_ref().and_then(|a| a.sprite(path, color_key));
+        let sprite = match packed {
+            Some(sprite) => sprite,
+            None => {
+                let mut sprite = Sprite::new();
+                match sprite.load_from_file(self.texture_creator, path, color_key) {
+                    Ok(()) => sprite,
+                    Err(msg) => {
+                        self.report_missing(path, &msg);
+                        Sprite::placeholder(self.placeholder.clone())
+                    }
+                }
+            }
+        };
+        let handle = Handle::new(sprite);
+        self.sprites.insert(key, Entry { handle: handle.clone(), modified });
+        handle
+    }
+    // the sheet at `path`, cut up as its sidecar file says, or else into frames `frame_size` pixels big
+    pub fn animated_sprite(&mut self, path: &str, frame_size: Option<(usize, usize)>, color_key: Option<Color>)
+                           -> AnimationHandle<'a> {
+        let key = (path.to_string(), frame_size, color_key);
+        if let Some(entry) = self.animations.get(&key) {
+            return entry.handle.clone();
+        }
+        let modified = sheet_modified(path);
+        let mut sprite = AnimatedSprite::new();
+        sprite.set_fixed_frame_size(frame_size);
+        if let Err(msg) = sprite.load_from_file(self.texture_creator, path, color_key) {
+            self.report_missing(path, &msg);
+            // a single placeholder frame
+            let (w, h) = frame_size.unwrap_or((PLACEHOLDER_SIZE as usize, PLACEHOLDER_SIZE as usize));
+            sprite.set_sheet_info(None);
+            sprite.set_fixed_frame_size(Some((w, h)));
+            let loaded = placeholder_surface(w as u32, h as u32)
+                .and_then(|surface| sprite.load_from_surface(self.texture_creator, surface));
+            if let Err(msg) = loaded {
+                println!("Could not make a placeholder for {}: {}", path, msg);
+            }
+        }
+        let handle = Handle::new(sprite);
+        self.animations.insert(key, Entry { handle: handle.clone(), modified });
+        handle
+    }
+    // while watching, `reload_changed` picks up images changed on disk
+    pub fn set_watching(&mut self, watching: bool) {
+        self.watching = watching;
+    }
+    // loads every image whose file changed since it was loaded, in place, so that handles to it see
+    // the new pixels; checks at most every `WATCH_INTERVAL` and only while watching. returns how many
+    // images were loaded again
+    pub fn reload_changed(&mut self) -> usize {
+        if !self.watching || self.last_check.elapsed() < WATCH_INTERVAL {
+            return 0;
+        }
+        self.last_check = Instant::now();
+        let texture_creator = self.texture_creator;
+        let missing = &mut self.missing;
+        let mut reloaded = 0;
+        for ((path, color_key), entry) in self.sprites.iter_mut() {
+            if !changed(modified(path), &mut entry.modified) {
+                continue;
+            }
+            // mods live on the sprite, so they survive the new texture
+            match entry.handle.get_mut().load_from_file(texture_creator, path, *color_key) {
+                Ok(()) => {
+                    missing.remove(path);
+                    reloaded += 1;
+                    println!("Reloaded {}", path);
+                }
+                Err(msg) => println!("Could not reload {}: {}", path, msg),
+            }
+        }
+        for ((path, frame_size, color_key), entry) in self.animations.iter_mut() {
+            if !changed(sheet_modified(path), &mut entry.modified) {
+                continue;
+            }
+            let mut sprite = entry.handle.get_mut();
+            // a placeholder was cut to its own size
+            sprite.set_fixed_frame_size(*frame_size);
+            match sprite.load_from_file(texture_creator, path, *color_key) {
+                Ok(()) => {
+                    missing.remove(path);
+                    reloaded += 1;
+                    println!("Reloaded {}", path);
+                }
+                Err(msg) => println!("Could not reload {}: {}", path, msg),
+            }
+        }
+        reloaded
+    }
+    fn report_missing(&mut self, path: &str, msg: &str) {
+        if self.missing.insert(path.to_string()) {
+            println!("Missing asset {}: {}", path, msg);
+        }
+    }
+}
+
+// when the file at `path` was last changed, if it exists
+fn modified<P: AsRef<Path>>(path: P) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// when the sheet at `path` or its sidecar file was last changed, whichever is later
+fn sheet_modified(path: &str) -> Option<SystemTime> {
+    let sheet = modified(path)?;
+    Some(modified(SheetInfo::sidecar_path(path)).map_or(sheet, |sidecar| sheet.max(sidecar)))
+}
+
+// whether a file last changed at `now` changed since `last`, which is brought up to date; a file
+// that is still being written is loaded again once it is done, since that changes it again
+fn changed(now: Option<SystemTime>, last: &mut Option<SystemTime>) -> bool {
+    if now.is_none() || now == *last {
+        return false;
+    }
+    *last = now;
+    true
+}
+
+// a w by h checkerboard of `PLACEHOLDER_COLOR` and black
+fn placeholder_surface(w: u32, h: u32) -> Result<Surface<'static>, String> {
+    let mut surface = Surface::new(w.max(1), h.max(1), PixelFormatEnum::RGBA32)?;
+    surface.fill_rect(None, Color::RGB(0, 0, 0))?;
+    let squares: Vec<Rect> = (0..h / PLACEHOLDER_SQUARE + 1)
+        .flat_map(|j| (0..w / PLACEHOLDER_SQUARE + 1).filter(move |i| (i + j) % 2 == 0).map(move |i| (i, j)))
+        .map(|(i, j)| Rect::new((i * PLACEHOLDER_SQUARE) as i32, (j * PLACEHOLDER_SQUARE) as i32,
+                                PLACEHOLDER_SQUARE, PLACEHOLDER_SQUARE))
+        .collect();
+    surface.fill_rects(&squares, PLACEHOLDER_COLOR)?;
+    Ok(surface)
+}
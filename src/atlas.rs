@@ -0,0 +1,309 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::rc::Rc;
+use serde::{Deserialize, Serialize};
+use sdl2::image::{LoadSurface, SaveSurface};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::TextureCreator;
+use sdl2::surface::Surface;
+use crate::liquid::LiquidKind;
+use crate::sprite::{SharedTexture, Sprite};
+use crate::tile_registry::TILE_REGISTRY;
+use crate::wall::WALL_REGISTRY;
+
+pub const SPRITE_DIR: &str = "sprites";
+pub const ATLAS_DIR: &str = "atlas";
+pub const ATLAS_MANIFEST: &str = "atlas/manifest.toml";
+const MANIFEST_HEADER: &str = "\
+# written by `--pack-atlas`; do not edit by hand
+# pages: page images, relative to this file, in page order
+# [regions.\"<path>\"]: where the image at <path> ended up
+#   page: index into pages
+#   x, y, w, h: the image's rect on that page, in pixels; padding and extruded edges lie outside it
+#   color_key: [r, g, b] of the pixels made transparent, if any; the region only stands in for
+#              the image when it is loaded with the same color key
+";
+
+// how images are packed onto pages
+pub struct PackSettings {
+    // pages are square, this many pixels on a side; most renderers take textures up to 4096
+    pub page_size: u32,
+    // empty pixels between neighboring images
+    pub padding: u32,
+    // how many times each image's edge pixels are repeated around it, so scaled or filtered
+    // drawing does not pick up its neighbors
+    pub extrude: u32,
+    // images wider or taller than this are left out and keep their own textures
+    pub max_size: u32,
+}
+
+impl PackSettings {
+    pub fn new() -> PackSettings {
+        PackSettings {
+            page_size: 4096,
+            padding: 2,
+            extrude: 1,
+            max_size: 1024,
+        }
+    }
+}
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(Serialize)]
+#[derive(Deserialize)]
+pub struct Region {
+    pub page: usize,
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+    // pixels of this color were made transparent, as when loading with a color key
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_key: Option<[u8; 3]>,
+}
+
+#[derive(Serialize)]
+#[derive(Deserialize)]
+pub struct Manifest {
+    pub pages: Vec<String>,
+    pub regions: BTreeMap<String, Region>,
+}
+
+// skyline bottom-left packing: the top edge of everything placed so far is kept as a list of
+// horizontal segments, and each rect goes where its bottom ends up lowest
+struct Skyline {
+    width: u32,
+    height: u32,
+    // (x, y, width) of each segment, left to right, covering the whole width
+    segments: Vec<(u32, u32, u32)>,
+}
+
+impl Skyline {
+    fn new(width: u32, height: u32) -> Skyline {
+        Skyline { width, height, segments: vec![(0, 0, width)] }
+    }
+    // the lowest top a w by h rect can have with its left edge at segment `i`
+    fn fit(&self, i: usize, w: u32, h: u32) -> Option<u32> {
+        let x = self.segments[i].0;
+        if x + w > self.width {
+            return None;
+        }
+        let mut y = 0;
+        let mut left = w;
+        for &(_, sy, sw) in &self.segments[i..] {
+            if left == 0 {
+                break;
+            }
+            y = y.max(sy);
+            left = left.saturating_sub(sw);
+        }
+        if y + h > self.height { None } else { Some(y) }
+    }
+    fn insert(&mut self, w: u32, h: u32) -> Option<(u32, u32)> {
+        // lowest bottom first, then the narrowest segment to waste less
+        let (i, y) = (0..self.segments.len())
+            .filter_map(|i| self.fit(i, w, h).map(|y| (i, y)))
+            .min_by_key(|&(i, y)| (y + h, self.segments[i].2))?;
+        let x = self.segments[i].0;
+        // the new segment replaces everything under it, and the one it ends in is cut short
+        let mut j = i;
+        while j < self.segments.len() && self.segments[j].0 < x + w {
+            let (sx, sy, sw) = self.segments[j];
+            if sx + sw > x + w {
+                self.segments[j] = (x + w, sy, sx + sw - (x + w));
+                break;
+            }
+            j += 1;
+        }
+        self.segments.splice(i..j, std::iter::once((x, y + h, w)));
+        // neighbors at the same height become one
+        self.segments.dedup_by(|next, prev| {
+            if prev.1 == next.1 {
+                prev.2 += next.2;
+                true
+            } else {
+                false
+            }
+        });
+        Some((x, y))
+    }
+    // how far down anything has been placed
+    fn used_height(&self) -> u32 {
+        self.segments.iter().map(|s| s.1).max().unwrap_or(0)
+    }
+}
+
+// the size of a png from its header, without decoding it
+fn png_size(path: &str) -> Result<(u32, u32), String> {
+    let mut header = [0u8; 24];
+    fs::File::open(path).and_then(|mut f| f.read_exact(&mut header))
+        .map_err(|e| format!("Could not read {}: {}", path, e))?;
+    if &header[1..4] != b"PNG" || &header[12..16] != b"IHDR" {
+        return Err(format!("{} is not a png", path));
+    }
+    let word = |at: usize| u32::from_be_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]]);
+    Ok((word(16), word(20)))
+}
+
+// packs the images at `files`, each with the color key it is loaded with, onto as few pages as fit
+// them and writes the pages and a manifest to `out_dir`; images that are too big or cannot be read
+// are left out with a message
+pub fn pack(files: &[(String, Option<Color>)], out_dir: &str, settings: &PackSettings) -> Result<Manifest, String> {
+    let border = 2 * settings.extrude + settings.padding;
+    if settings.max_size + border > settings.page_size {
+        return Err(format!("Images up to {} pixels do not fit on {} pixel pages", settings.max_size, settings.page_size));
+    }
+    let mut sized = Vec::new();
+    for (file, color_key) in files {
+        match png_size(file) {
+            Ok((w, h)) if w.max(h) <= settings.max_size && w > 0 && h > 0 => sized.push((file, w, h, *color_key)),
+            Ok((w, h)) => println!("Not packing {}: it is {}x{}", file, w, h),
+            Err(msg) => println!("Not packing {}", msg),
+        }
+    }
+    // tall images first packs tighter
+    sized.sort_by(|a, b| (b.2, b.1).cmp(&(a.2, a.1)).then(a.0.cmp(b.0)));
+    let mut pages: Vec<Skyline> = Vec::new();
+    let mut placed: Vec<Vec<(&String, Region)>> = Vec::new();
+    for &(file, w, h, color_key) in &sized {
+        let cell = (w + border, h + border);
+        let spot = pages.iter_mut().enumerate().find_map(|(i, p)| p.insert(cell.0, cell.1).map(|at| (i, at)));
+        let (page, (x, y)) = match spot {
+            Some(s) => s,
+            None => {
+                let mut skyline = Skyline::new(settings.page_size, settings.page_size);
+                let at = skyline.insert(cell.0, cell.1).ok_or_else(|| format!("{} does not fit on a page", file))?;
+                pages.push(skyline);
+                placed.push(Vec::new());
+                (pages.len() - 1, at)
+            }
+        };
+        let e = settings.extrude as i32;
+        let color_key = color_key.map(|k| [k.r, k.g, k.b]);
+        placed[page].push((file, Region { page, x: x as i32 + e, y: y as i32 + e, w, h, color_key }));
+    }
+    fs::create_dir_all(out_dir).map_err(|e| format!("Could not create {}: {}", out_dir, e))?;
+    let mut manifest = Manifest { pages: Vec::new(), regions: BTreeMap::new() };
+    for (i, regions) in placed.iter().enumerate() {
+        let (w, h) = (settings.page_size, pages[i].used_height().max(1));
+        let mut pixels = vec![0u8; (w * h * 4) as usize];
+        for (file, region) in regions {
+            if let Err(msg) = blit(&mut pixels, w, file, region, settings) {
+                println!("Not packing {}", msg);
+                continue;
+            }
+            manifest.regions.insert(file.to_string(), *region);
+        }
+        let name = format!("atlas_{}.png", i);
+        let path = Path::new(out_dir).join(&name);
+        let surface = Surface::from_data(&mut pixels, w, h, w * 4, PixelFormatEnum::RGBA32)?;
+        surface.save(&path).map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+        manifest.pages.push(name);
+    }
+    let text = toml::to_string(&manifest).map_err(|e| e.to_string())?;
+    let path = Path::new(out_dir).join("manifest.toml");
+    fs::write(&path, format!("{}\n{}", MANIFEST_HEADER, text))
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+    Ok(manifest)
+}
+
+// packs every png in the sprites directory into the atlas directory, keyed the way the game loads it
+pub fn pack_sprites() -> Result<Manifest, String> {
+    let mut files: Vec<String> = fs::read_dir(SPRITE_DIR).map_err(|e| format!("Could not list {}: {}", SPRITE_DIR, e))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".png"))
+        .map(|name| format!("{}/{}", SPRITE_DIR, name))
+        .collect();
+    files.sort();
+    // tiles, walls and liquids are loaded with magenta as their color key, everything else with none
+    let magenta = Color::RGB(255, 0, 255);
+    let keyed: HashSet<&str> = TILE_REGISTRY.iter().filter_map(|t| t.texture.as_deref())
+        .chain(WALL_REGISTRY.iter().filter_map(|w| w.texture.as_deref()))
+        .chain(LiquidKind::ALL.iter().map(|k| k.texture()))
+        .collect();
+    let files: Vec<(String, Option<Color>)> = files.into_iter()
+        .map(|file| {
+            let color_key = if keyed.contains(file.as_str()) { Some(magenta) } else { None };
+            (file, color_key)
+        })
+        .collect();
+    pack(&files, ATLAS_DIR, &PackSettings::new())
+}
+
+// copies the image at `file` into `region` of a page `page_w` pixels wide, with its edges repeated
+// outwards `settings.extrude` times and the region's color key made transparent
+fn blit(page: &mut [u8], page_w: u32, file: &str, region: &Region, settings: &PackSettings) -> Result<(), String> {
+    let surface = Surface::from_file(file).map_err(|e| format!("{}: {}", file, e))?;
+    // sdl2 converts between formats described by a surface, so borrow one from an empty surface
+    let rgba = Surface::new(1, 1, PixelFormatEnum::RGBA32)?;
+    let surface = surface.convert(&rgba.pixel_format()).map_err(|e| format!("{}: {}", file, e))?;
+    let (w, h) = (surface.width() as i32, surface.height() as i32);
+    if (w as u32, h as u32) != (region.w, region.h) {
+        return Err(format!("{} changed size while packing", file));
+    }
+    let pitch = surface.pitch() as usize;
+    let source = surface.without_lock().ok_or_else(|| format!("{}: could not read the pixels", file))?;
+    let e = settings.extrude as i32;
+    for dy in -e..h + e {
+        for dx in -e..w + e {
+            let (sx, sy) = (dx.clamp(0, w - 1) as usize, dy.clamp(0, h - 1) as usize);
+            let from = sy * pitch + sx * 4;
+            let mut pixel = [source[from], source[from + 1], source[from + 2], source[from + 3]];
+            if region.color_key.is_some_and(|k| pixel[..3] == k) {
+                pixel = [0; 4];
+            }
+            let to = (((region.y + dy) as u32 * page_w + (region.x + dx) as u32) * 4) as usize;
+            page[to..to + 4].copy_from_slice(&pixel);
+        }
+    }
+    Ok(())
+}
+
+// packed pages loaded as textures, handing out their regions as sprites
+pub struct Atlas<'a> {
+    pages: Vec<SharedTexture<'a>>,
+    regions: HashMap<String, Region>,
+}
+
+impl<'a> Atlas<'a> {
+    pub fn load<C>(texture_creator: &'a TextureCreator<C>, manifest_path: &str) -> Result<Atlas<'a>, String> {
+        let text = fs::read_to_string(manifest_path).map_err(|e| format!("Could not read {}: {}", manifest_path, e))?;
+        let manifest: Manifest = toml::from_str(&text).map_err(|e| format!("{}: {}", manifest_path, e))?;
+        let dir = Path::new(manifest_path).parent().unwrap_or_else(|| Path::new(""));
+        let mut pages = Vec::new();
+        for name in &manifest.pages {
+            let path = dir.join(name);
+            let surface = Surface::from_file(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let texture = texture_creator.create_texture_from_surface(surface).map_err(|e| e.to_string())?;
+            pages.push(Rc::new(RefCell::new(texture)));
+        }
+        if let Some((name, _)) = manifest.regions.iter().find(|(_, r)| r.page >= pages.len()) {
+            return Err(format!("{}: {} is on a page that does not exist", manifest_path, name));
+        }
+        Ok(Atlas { pages, regions: manifest.regions.into_iter().collect() })
+    }
+    // the atlas in `ATLAS_MANIFEST`, if the sprites have been packed
+    pub fn load_packed<C>(texture_creator: &'a TextureCreator<C>) -> Option<Atlas<'a>> {
+        if !Path::new(ATLAS_MANIFEST).exists() {
+            return None;
+        }
+        Atlas::load(texture_creator, ATLAS_MANIFEST).map_err(|msg| println!("{}", msg)).ok()
+    }
+    // the image packed from `path`, if it was packed with `color_key`
+    pub fn sprite(&self, path: &str, color_key: Option<Color>) -> Option<Sprite<'a>> {
+        let region = self.regions.get(path)?;
+        if region.color_key != color_key.map(|k| [k.r, k.g, k.b]) {
+            return None;
+        }
+        let rect = Rect::new(region.x, region.y, region.w, region.h);
+        Some(Sprite::from_region(self.pages[region.page].clone(), rect))
+    }
+}
@@ -0,0 +1,111 @@
+use sdl2::rect::Rect;
+use crate::tile::TileId;
+
+// every shape comes in this many interchangeable variants, side by side or stacked
+pub const VARIANTS: u32 = 3;
+
+// bits of a tile's neighbor bitfield; a bit is set when that neighbor connects to the tile
+pub const LEFT: u32 = 1;
+pub const DOWN: u32 = 1 << 1;
+pub const RIGHT: u32 = 1 << 2;
+pub const UP: u32 = 1 << 3;
+pub const UP_LEFT: u32 = 1 << 4;
+pub const UP_RIGHT: u32 = 1 << 5;
+pub const DOWN_LEFT: u32 = 1 << 6;
+pub const DOWN_RIGHT: u32 = 1 << 7;
+
+// how a sheet arranges its frames
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum SheetLayout {
+    // Terraria's block sheets, with inner corners and one tile wide strips; see `frame_for`
+    Terraria,
+    // one row per combination of the four side neighbors, numbered by their bits, and one column
+    // per variant, as in the `Tile_*.png` sheets
+    Sides,
+}
+
+impl SheetLayout {
+    pub fn from_name(name: &str) -> Option<SheetLayout> {
+        match name {
+            "terraria" => Some(SheetLayout::Terraria),
+            "sides" => Some(SheetLayout::Sides),
+            _ => None,
+        }
+    }
+}
+
+// whether a tile of kind `tile` draws as joined to a neighbor of kind `neighbor`: either they are
+// the same kind, or the registry lists them as merging so their edges do not show as seams
+pub fn merges(tile: TileId, neighbor: TileId) -> bool {
+    if tile == TileId::NOTHING || neighbor == TileId::NOTHING {
+        false
+    } else {
+        tile == neighbor || tile.info().merge.contains(&neighbor)
+    }
+}
+
+// stable pseudo-random variant for a position, so that a tile keeps its look across redraws,
+// edits elsewhere and save/load
+pub fn variant_at(x: usize, y: usize) -> u32 {
+    let mut h = (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ (y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+    h ^= h >> 29;
+    h = h.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h ^= h >> 32;
+    (h % u64::from(VARIANTS)) as u32
+}
+
+// the (column, row) of the frame in a Terraria block sheet for a neighbor bitfield and variant
+pub fn frame_for(neighbor_bf: u32, variant: u32) -> (u32, u32) {
+    let r = variant % VARIANTS;
+    let has = |bit: u32| neighbor_bf & bit != 0;
+    match (has(UP), has(DOWN), has(LEFT), has(RIGHT)) {
+        (true, true, true, true) => {
+            // fully surrounded; missing diagonals get their own inner-corner frames
+            if !has(UP_LEFT) && !has(UP_RIGHT) {
+                (6 + r, 1)
+            } else if !has(DOWN_LEFT) && !has(DOWN_RIGHT) {
+                (6 + r, 2)
+            } else if !has(UP_LEFT) && !has(DOWN_LEFT) {
+                (10, r)
+            } else if !has(UP_RIGHT) && !has(DOWN_RIGHT) {
+                (11, r)
+            } else {
+                (1 + r, 1)
+            }
+        }
+        // edges
+        (false, true, true, true) => (1 + r, 0),
+        (true, false, true, true) => (1 + r, 2),
+        (true, true, false, true) => (0, r),
+        (true, true, true, false) => (4, r),
+        // outer corners
+        (false, true, false, true) => (2 * r, 3),
+        (false, true, true, false) => (1 + 2 * r, 3),
+        (true, false, false, true) => (2 * r, 4),
+        (true, false, true, false) => (1 + 2 * r, 4),
+        // one tile wide strips
+        (true, true, false, false) => (5, r),
+        (false, false, true, true) => (6 + r, 4),
+        // ends of strips
+        (false, true, false, false) => (6 + r, 0),
+        (true, false, false, false) => (6 + r, 3),
+        (false, false, false, true) => (9, r),
+        (false, false, true, false) => (12, r),
+        // nothing around it
+        (false, false, false, false) => (9 + r, 3),
+    }
+}
+
+// source rectangle in a sheet with the given layout, frame size and padding for a neighbor bitfield
+// and variant
+pub fn frame_rect(layout: SheetLayout, frame_size: u32, frame_padding: u32, neighbor_bf: u32, variant: u32) -> Rect {
+    let (col, row) = match layout {
+        SheetLayout::Terraria => frame_for(neighbor_bf, variant),
+        SheetLayout::Sides => (variant % VARIANTS, neighbor_bf & (LEFT | DOWN | RIGHT | UP)),
+    };
+    let stride = frame_size + frame_padding;
+    Rect::new((col * stride) as i32, (row * stride) as i32, frame_size, frame_size)
+}
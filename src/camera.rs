@@ -0,0 +1,170 @@
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use crate::tile::TILE_SIZE;
+use crate::viewport::Viewport;
+use crate::world::World;
+
+// keyboard panning speed in screen pixels per second, so it feels the same at every zoom level
+const PAN_SPEED: f64 = 900.0;
+// how quickly the view catches up with where it is headed; higher is snappier. The view closes
+// `1 - e^(-rate * dt)` of the remaining distance every frame, which adds up to the same motion
+// whatever the frame rate
+const PAN_EASING: f64 = 12.0;
+const ZOOM_EASING: f64 = 14.0;
+// each notch of the mouse wheel scales the zoom by this much
+const ZOOM_STEP: f64 = 1.15;
+const MIN_ZOOM: f64 = 0.25;
+const MAX_ZOOM: f64 = 4.0;
+
+// moves a `Viewport` around from input events: IJKL pan, the mouse wheel zooms in and out around
+// the cursor, and dragging with the middle button pans directly. It follows a point until panning
+// or dragging lets go of it; F toggles between following and the free camera
+pub struct Camera {
+    // where the view is easing towards, in tile coordinates
+    target: (f64, f64),
+    target_zoom: f64,
+    // held keys: left, right, up, down
+    held: [bool; 4],
+    // last known cursor position, in pixels
+    cursor: (i32, i32),
+    // whether the middle button is held
+    dragging: bool,
+    // the screen pixel a zoom is anchored at, and the tile point that has to stay under it
+    zoom_anchor: Option<((i32, i32), (f64, f64))>,
+    // the tile point kept in the middle of the view, if any
+    follow: Option<(f64, f64)>,
+    // whether the view has let go of `follow`
+    free: bool,
+}
+
+impl Camera {
+    // starts out wherever `view` is looking now
+    pub fn new(view: &Viewport) -> Camera {
+        Camera {
+            target: view.get_center(),
+            target_zoom: view.get_zoom(),
+            held: [false; 4],
+            cursor: (0, 0),
+            dragging: false,
+            zoom_anchor: None,
+            follow: None,
+            free: false,
+        }
+    }
+    pub fn set_follow(&mut self, follow: Option<(f64, f64)>) {
+        self.follow = follow;
+    }
+    // `prev_view` is the view drawing interpolates from; dragging moves it along with `view`
+    pub fn handle_event(&mut self, event: &Event, view: &mut Viewport, prev_view: &mut Viewport) {
+        match *event {
+            Event::KeyDown { keycode: Some(Keycode::F), repeat: false, .. } => self.free = !self.free,
+            Event::KeyDown { keycode: Some(key), .. } => self.set_held(key, true),
+            Event::KeyUp { keycode: Some(key), .. } => self.set_held(key, false),
+            Event::MouseWheel { y, .. } if y != 0 => {
+                self.target_zoom = (self.target_zoom * ZOOM_STEP.powi(y)).clamp(MIN_ZOOM, MAX_ZOOM);
+                let (x, y) = self.cursor;
+                self.zoom_anchor = Some(((x, y), view.screen_to_tile(x, y)));
+            }
+            Event::MouseButtonDown { mouse_btn: MouseButton::Middle, x, y, .. } => {
+                self.dragging = true;
+                self.cursor = (x, y);
+            }
+            Event::MouseButtonUp { mouse_btn: MouseButton::Middle, .. } => {
+                self.dragging = false;
+            }
+            Event::MouseMotion { x, y, .. } => {
+                if self.dragging {
+                    // dragging follows the cursor exactly, so it skips the easing and the
+                    // interpolation between ticks
+                    let tile_pixels = view.tile_pixels();
+                    let dx = f64::from(x - self.cursor.0) / tile_pixels;
+                    let dy = f64::from(y - self.cursor.1) / tile_pixels;
+                    for v in [&mut *view, &mut *prev_view] {
+                        let (cx, cy) = v.get_center();
+                        v.set_center(cx - dx, cy - dy);
+                    }
+                    self.target = (self.target.0 - dx, self.target.1 - dy);
+                    self.zoom_anchor = None;
+                    self.free = true;
+                }
+                self.cursor = (x, y);
+            }
+            _ => {}
+        }
+    }
+    fn set_held(&mut self, key: Keycode, down: bool) {
+        let index = match key {
+            Keycode::J => 0,
+            Keycode::L => 1,
+            Keycode::I => 2,
+            Keycode::K => 3,
+            _ => return,
+        };
+        self.held[index] = down;
+        if down {
+            self.free = true;
+        }
+    }
+    // advances the camera by `dt` seconds and moves `view` to match
+    pub fn update(&mut self, dt: f64, view: &mut Viewport, world: &World) {
+        let dir_x = f64::from(self.held[1] as u8) - f64::from(self.held[0] as u8);
+        let dir_y = f64::from(self.held[3] as u8) - f64::from(self.held[2] as u8);
+        if let (Some(point), false) = (self.follow, self.free) {
+            // zooming keeps the followed point in the middle rather than under the cursor
+            self.target = point;
+            self.zoom_anchor = None;
+        } else if dir_x != 0.0 || dir_y != 0.0 {
+            let step = PAN_SPEED * dt / pixels_per_tile(self.target_zoom);
+            self.target = (self.target.0 + dir_x * step, self.target.1 + dir_y * step);
+            self.zoom_anchor = None;
+        }
+        let zoom = ease(view.get_zoom(), self.target_zoom, ZOOM_EASING, dt);
+        view.set_zoom(zoom);
+        let (cx, cy) = view.get_center();
+        let (mut x, mut y) = (ease(cx, self.target.0, PAN_EASING, dt), ease(cy, self.target.1, PAN_EASING, dt));
+        if let Some(((sx, sy), (ax, ay))) = self.zoom_anchor {
+            // keep the anchored point under the same pixel, both now and once the zoom settles
+            let size = view.get_output_dimensions();
+            let (off_x, off_y) = (f64::from(sx) - f64::from(size.w) / 2.0, f64::from(sy) - f64::from(size.h) / 2.0);
+            x = ax - off_x / view.tile_pixels();
+            y = ay - off_y / view.tile_pixels();
+            let target_pixels = pixels_per_tile(self.target_zoom);
+            self.target = (ax - off_x / target_pixels, ay - off_y / target_pixels);
+            if (zoom - self.target_zoom).abs() < 1e-3 {
+                self.zoom_anchor = None;
+            }
+        }
+        self.target = clamp_center(self.target, view, pixels_per_tile(self.target_zoom), world);
+        let (x, y) = clamp_center((x, y), view, view.tile_pixels(), world);
+        view.set_center(x, y);
+    }
+}
+
+// frame-rate independent exponential approach from `from` to `to`
+fn ease(from: f64, to: f64, rate: f64, dt: f64) -> f64 {
+    let t = 1.0 - (-rate * dt).exp();
+    let eased = from + (to - from) * t;
+    if (to - eased).abs() < 1e-4 { to } else { eased }
+}
+
+// size of a tile on screen at a zoom level
+fn pixels_per_tile(zoom: f64) -> f64 {
+    f64::from(TILE_SIZE) * zoom
+}
+
+// keeps a view with tiles `tile_pixels` wide inside the world; a world smaller than the view is
+// centered instead
+fn clamp_center(center: (f64, f64), view: &Viewport, tile_pixels: f64, world: &World) -> (f64, f64) {
+    let size = view.get_output_dimensions();
+    let half_w = f64::from(size.w) / 2.0 / tile_pixels;
+    let half_h = f64::from(size.h) / 2.0 / tile_pixels;
+    let clamp = |c: f64, half: f64, extent: f64| {
+        if half * 2.0 >= extent {
+            extent / 2.0
+        } else {
+            c.max(half).min(extent - half)
+        }
+    };
+    (clamp(center.0, half_w, world.width() as f64), clamp(center.1, half_h, world.height() as f64))
+}
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::path::Path;
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use sdl2::render::{Canvas, RenderTarget};
+use crate::assets::{AnimationHandle, Assets};
+use crate::common::Size;
+
+// the clips every body, armor and accessory sheet's sidecar file names, one for each pose
+const POSE_CLIPS: [&str; 4] = ["stand", "use", "jump", "walk"];
+// hair sheets are not animated; their first frame moves with the head, which rises by this many
+// pixels on some walk frames
+const HEAD_BOB: i32 = -2;
+const HEAD_BOB_FRAMES: [usize; 6] = [7, 8, 9, 14, 15, 16];
+const WHITE: Color = Color { r: 255, g: 255, b: 255, a: 255 };
+
+// what the body is doing, which picks the clip every layer draws from
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub enum Pose {
+    Stand,
+    // how far through swinging the arm, from 0 to 1
+    Use(f64),
+    Jump,
+    // steps taken; the walk clip starts over once it runs out
+    Walk(u32),
+}
+
+impl Pose {
+    fn clip_name(self) -> &'static str {
+        match self {
+            Pose::Stand => "stand",
+            Pose::Use(_) => "use",
+            Pose::Jump => "jump",
+            Pose::Walk(_) => "walk",
+        }
+    }
+}
+
+// how a character looks without anything equipped
+#[derive(Clone)]
+pub struct Appearance {
+    // body style 0 to 9, which picks the Player_N_* sheets; styles only redraw some parts and
+    // use style 0 for the rest
+    pub style: u32,
+    // wears the Female_Body_* cut of body armor
+    pub female: bool,
+    // Player_Hair_N, from 1
+    pub hair: u32,
+    pub hair_color: Color,
+    pub skin_color: Color,
+    pub eye_color: Color,
+    pub shirt_color: Color,
+    pub undershirt_color: Color,
+    pub pants_color: Color,
+    pub shoe_color: Color,
+}
+
+impl Appearance {
+    // Terraria's default look
+    pub fn new() -> Appearance {
+        Appearance {
+            style: 0,
+            female: false,
+            hair: 1,
+            hair_color: Color::RGB(215, 90, 55),
+            skin_color: Color::RGB(255, 125, 90),
+            eye_color: Color::RGB(105, 90, 75),
+            shirt_color: Color::RGB(175, 165, 140),
+            undershirt_color: Color::RGB(160, 180, 215),
+            pants_color: Color::RGB(255, 230, 175),
+            shoe_color: Color::RGB(160, 105, 60),
+        }
+    }
+}
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub enum AccessorySlot {
+    Back,
+    // worn on the far hand, behind the body
+    HandsOff,
+    Shoes,
+    Waist,
+    Neck,
+    Face,
+    // worn on the near hand, over the arm
+    HandsOn,
+    Front,
+    Shield,
+}
+
+impl AccessorySlot {
+    fn sheet_prefix(self) -> &'static str {
+        match self {
+            AccessorySlot::Back => "Acc_Back",
+            AccessorySlot::HandsOff => "Acc_HandsOff",
+            AccessorySlot::Shoes => "Acc_Shoes",
+            AccessorySlot::Waist => "Acc_Waist",
+            AccessorySlot::Neck => "Acc_Neck",
+            AccessorySlot::Face => "Acc_Face",
+            AccessorySlot::HandsOn => "Acc_HandsOn",
+            AccessorySlot::Front => "Acc_Front",
+            AccessorySlot::Shield => "Acc_Shield",
+        }
+    }
+}
+
+// armor pieces and accessories by sheet number; armor replaces the clothes it covers
+#[derive(Clone)]
+#[derive(Default)]
+pub struct Equipment {
+    pub head: Option<u32>,
+    pub body: Option<u32>,
+    pub legs: Option<u32>,
+    pub accessories: Vec<(AccessorySlot, u32)>,
+}
+
+impl Equipment {
+    pub fn new() -> Equipment {
+        Equipment::default()
+    }
+}
+
+struct Layer<'a> {
+    sprite: AnimationHandle<'a>,
+    // sheets are shared with other characters, so the tint is set again for every draw
+    tint: Color,
+    // draws the first frame shifted with the head instead of following the pose
+    follows_head: bool,
+    // whether the sheet has clips for the poses; the others draw their first frame
+    animated: bool,
+}
+
+// a character put together from body, hair, armor and accessory sheets, drawn back to front so
+// that every layer shows the same frame
+pub struct Character<'a> {
+    layers: Vec<Layer<'a>>,
+    // the frames of each pose's clip, as (column, row)
+    clips: HashMap<&'static str, Vec<(usize, usize)>>,
+}
+
+impl<'a> Character<'a> {
+    // loads the sheets for a look and outfit; sheets that fail to load show as placeholders
+    pub fn load<C>(assets: &mut Assets<'a, C>, look: &Appearance, gear: &Equipment) -> Character<'a> {
+        let mut character = Character { layers: Vec::new(), clips: HashMap::new() };
+        let accessories = |slot: AccessorySlot| gear.accessories.iter()
+            .filter(move |&&(s, _)| s == slot)
+            .map(move |&(_, n)| format!("sprites/{}_{}.png", slot.sheet_prefix(), n));
+        let body = |part: u32| body_part_path(look.style, part);
+        let mut add = |path: String, tint: Color| character.add_layer(assets, &path, tint, false);
+        for path in accessories(AccessorySlot::Back).chain(accessories(AccessorySlot::HandsOff)) {
+            add(path, WHITE);
+        }
+        // legs and feet
+        add(body(10), look.skin_color);
+        if let Some(n) = gear.legs {
+            add(format!("sprites/Armor_Legs_{}.png", n), WHITE);
+        } else {
+            add(body(11), look.pants_color);
+            add(body(12), look.shoe_color);
+        }
+        for path in accessories(AccessorySlot::Shoes) {
+            add(path, WHITE);
+        }
+        // torso
+        add(body(3), look.skin_color);
+        if let Some(n) = gear.body {
+            let cut = if look.female { "Female_Body" } else { "Armor_Body" };
+            add(format!("sprites/{}_{}.png", cut, n), WHITE);
+        } else {
+            add(body(4), look.undershirt_color);
+            add(body(6), look.shirt_color);
+        }
+        add(body(5), look.skin_color);
+        for path in accessories(AccessorySlot::Waist).chain(accessories(AccessorySlot::Neck)) {
+            add(path, WHITE);
+        }
+        // head
+        add(body(0), look.skin_color);
+        add(body(1), WHITE);
+        add(body(2), look.eye_color);
+        character.add_layer(assets, &format!("sprites/Player_Hair_{}.png", look.hair), look.hair_color, true);
+        let mut add = |path: String, tint: Color| character.add_layer(assets, &path, tint, false);
+        for path in accessories(AccessorySlot::Face) {
+            add(path, WHITE);
+        }
+        if let Some(n) = gear.head {
+            add(format!("sprites/Armor_Head_{}.png", n), WHITE);
+        }
+        // the near arm goes over everything else on the body
+        add(body(7), look.skin_color);
+        if let Some(n) = gear.body {
+            add(format!("sprites/Armor_Arm_{}.png", n), WHITE);
+        } else {
+            add(body(8), look.undershirt_color);
+            add(body(13), look.shirt_color);
+        }
+        add(body(9), look.skin_color);
+        for slot in [AccessorySlot::HandsOn, AccessorySlot::Front, AccessorySlot::Shield].iter() {
+            for path in accessories(*slot) {
+                add(path, WHITE);
+            }
+        }
+        character.clips = character.pose_clips();
+        character
+    }
+    fn add_layer<C>(&mut self, assets: &mut Assets<'a, C>, path: &str, tint: Color, follows_head: bool) {
+        let sprite = assets.animated_sprite(path, None, None);
+        let animated = !follows_head && sprite.get().clips().next().is_some();
+        self.layers.push(Layer { sprite, tint, follows_head, animated });
+    }
+    // the pose clips of the first animated layer, leaving out any frame another animated layer
+    // does not have, so that every layer stays on the same frame
+    fn pose_clips(&self) -> HashMap<&'static str, Vec<(usize, usize)>> {
+        let sheets: Vec<_> = self.layers.iter().filter(|l| l.animated).map(|l| l.sprite.get()).collect();
+        let mut clips = HashMap::new();
+        if let Some(first) = sheets.first() {
+            for (name, clip) in first.clips() {
+                if let Some(&pose) = POSE_CLIPS.iter().find(|&&p| p == name) {
+                    let frames = clip.frames().iter().cloned()
+                        .filter(|&cell| sheets.iter().all(|s| s.has_cell(cell)))
+                        .collect();
+                    clips.insert(pose, frames);
+                }
+            }
+        }
+        clips
+    }
+    // the (column, row) every animated layer draws for `pose`
+    fn cell(&self, pose: Pose) -> (usize, usize) {
+        let frames = match self.clips.get(pose.clip_name()) {
+            Some(frames) if !frames.is_empty() => frames,
+            _ => return (0, 0),
+        };
+        let index = match pose {
+            Pose::Use(progress) => ((progress * frames.len() as f64) as usize).min(frames.len() - 1),
+            Pose::Walk(step) => step as usize % frames.len(),
+            Pose::Stand | Pose::Jump => 0,
+        };
+        frames[index]
+    }
+    // draws the frame for `pose` with the feet at (x, y), `scale` times the size of the sheets,
+    // mirrored when facing left
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, x: i32, y: i32, scale: f64, pose: Pose,
+                                   facing_right: bool) -> Result<(), String> {
+        let cell = self.cell(pose);
+        let head_bob = if HEAD_BOB_FRAMES.contains(&cell.1) { (f64::from(HEAD_BOB) * scale).round() as i32 } else { 0 };
+        for layer in &self.layers {
+            let mut sprite = layer.sprite.get_mut();
+            sprite.set_color_mod(layer.tint);
+            let size = Size {
+                w: (sprite.frame_width() as f64 * scale).round() as u32,
+                h: (sprite.frame_height() as f64 * scale).round() as u32,
+            };
+            let (at, cell) = if layer.follows_head {
+                (Point::new(x, y + head_bob), (0, 0))
+            } else if layer.animated {
+                (Point::new(x, y), cell)
+            } else {
+                (Point::new(x, y), (0, 0))
+            };
+            sprite.render_rotated(canvas, at, Some(size), cell, 0.0, !facing_right)?;
+        }
+        Ok(())
+    }
+}
+
+// the sheet for a body part in a style, falling back on style 0 where the style does not redraw it
+fn body_part_path(style: u32, part: u32) -> String {
+    let path = format!("sprites/Player_{}_{}.png", style, part);
+    if style != 0 && !Path::new(&path).exists() {
+        format!("sprites/Player_0_{}.png", part)
+    } else {
+        path
+    }
+}
@@ -0,0 +1,65 @@
+use crate::liquid::Liquid;
+use crate::tile::{Tile, TileId};
+use crate::wall::{Wall, WallId};
+
+// chunks are square; keep this a power of two so the coordinate math stays cheap
+pub const CHUNK_SIZE: usize = 32;
+pub const CHUNK_AREA: usize = CHUNK_SIZE * CHUNK_SIZE;
+
+// a fixed-size block of cells stored row by row, with one flat allocation per layer
+#[derive(Clone)]
+pub struct Chunk {
+    tiles: Vec<Tile>,
+    walls: Vec<Wall>,
+    liquids: Vec<Liquid>,
+}
+
+impl Chunk {
+    pub fn new() -> Chunk {
+        Chunk {
+            tiles: vec![Tile::new(); CHUNK_AREA],
+            walls: vec![Wall::new(); CHUNK_AREA],
+            liquids: vec![Liquid::new(); CHUNK_AREA],
+        }
+    }
+    fn index(x: usize, y: usize) -> usize {
+        debug_assert!(x < CHUNK_SIZE && y < CHUNK_SIZE);
+        y * CHUNK_SIZE + x
+    }
+    // coordinates are local to the chunk
+    pub fn get(&self, x: usize, y: usize) -> &Tile {
+        &self.tiles[Chunk::index(x, y)]
+    }
+    pub fn get_mut(&mut self, x: usize, y: usize) -> &mut Tile {
+        &mut self.tiles[Chunk::index(x, y)]
+    }
+    pub fn get_wall(&self, x: usize, y: usize) -> &Wall {
+        &self.walls[Chunk::index(x, y)]
+    }
+    pub fn get_wall_mut(&mut self, x: usize, y: usize) -> &mut Wall {
+        &mut self.walls[Chunk::index(x, y)]
+    }
+    pub fn get_liquid(&self, x: usize, y: usize) -> &Liquid {
+        &self.liquids[Chunk::index(x, y)]
+    }
+    pub fn get_liquid_mut(&mut self, x: usize, y: usize) -> &mut Liquid {
+        &mut self.liquids[Chunk::index(x, y)]
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tiles.iter().all(|t| t.get_id() == TileId::NOTHING)
+            && self.walls.iter().all(|w| w.get_id() == WallId::NOTHING)
+            && self.liquids.iter().all(|l| l.is_empty())
+    }
+    // yields (local x, local y, tile)
+    pub fn iter(&self) -> impl Iterator<Item=(usize, usize, &Tile)> {
+        self.tiles.iter().enumerate().map(|(i, t)| (i % CHUNK_SIZE, i / CHUNK_SIZE, t))
+    }
+    // yields (local x, local y, liquid)
+    pub fn iter_liquids(&self) -> impl Iterator<Item=(usize, usize, &Liquid)> {
+        self.liquids.iter().enumerate().map(|(i, l)| (i % CHUNK_SIZE, i / CHUNK_SIZE, l))
+    }
+    // yields (local x, local y, wall)
+    pub fn iter_walls(&self) -> impl Iterator<Item=(usize, usize, &Wall)> {
+        self.walls.iter().enumerate().map(|(i, w)| (i % CHUNK_SIZE, i / CHUNK_SIZE, w))
+    }
+}
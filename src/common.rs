@@ -1,8 +1,12 @@
+#[derive(Clone)]
+#[derive(Copy)]
 pub struct Position {
     pub x: i32,
     pub y: i32
 }
+#[derive(Clone)]
+#[derive(Copy)]
 pub struct Size {
     pub w: u32,
     pub h: u32
-}
\ No newline at end of file
+}
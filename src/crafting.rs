@@ -0,0 +1,172 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use serde::Deserialize;
+use crate::entity::Body;
+use crate::item::{Inventory, ItemId, ItemStack};
+use crate::item_registry::ITEM_REGISTRY;
+use crate::tile::TileId;
+use crate::tile_registry::TILE_REGISTRY;
+use crate::world::World;
+
+pub const RECIPE_FILE: &str = "data/recipes.toml";
+// how far from the edges of the player's hitbox a station's cell can be and still count, in tiles
+const STATION_RANGE_X: i64 = 4;
+const STATION_RANGE_Y: i64 = 3;
+
+lazy_static! {
+    // every recipe, read once from `RECIPE_FILE`
+    pub static ref RECIPE_BOOK: RecipeBook = RecipeBook::load_from_file(RECIPE_FILE)
+        .unwrap_or_else(|msg| panic!("ERROR: Could not load the recipes: {}", msg));
+}
+
+#[derive(Debug)]
+pub struct Recipe {
+    pub result: ItemStack,
+    // one stack per item, never empty
+    pub ingredients: Vec<ItemStack>,
+    // tile that has to be nearby; `None` for recipes made by hand
+    pub station: Option<TileId>,
+}
+
+// a `[[recipe]]` table as written in the recipe file
+#[derive(Deserialize)]
+struct RecipeEntry {
+    result: String,
+    #[serde(default = "default_count")]
+    count: u32,
+    station: Option<String>,
+    ingredients: Vec<IngredientEntry>,
+}
+
+#[derive(Deserialize)]
+struct IngredientEntry {
+    item: String,
+    #[serde(default = "default_count")]
+    count: u32,
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+#[derive(Deserialize)]
+struct RecipeFile {
+    #[serde(default)]
+    recipe: Vec<RecipeEntry>,
+}
+
+// the recipes, indexed by what they use so that finding the craftable ones only looks at recipes
+// the inventory has something for
+pub struct RecipeBook {
+    recipes: Vec<Recipe>,
+    by_ingredient: HashMap<ItemId, Vec<usize>>,
+    // every tile some recipe needs
+    stations: HashSet<TileId>,
+}
+
+impl RecipeBook {
+    pub fn load_from_file(path: &str) -> Result<RecipeBook, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        RecipeBook::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+    pub fn parse(text: &str) -> Result<RecipeBook, String> {
+        let file: RecipeFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        let mut book = RecipeBook {
+            recipes: Vec::new(),
+            by_ingredient: HashMap::new(),
+            stations: HashSet::new(),
+        };
+        let item = |name: &str| ITEM_REGISTRY.id(name).ok_or_else(|| format!("Unknown item {}", name));
+        for entry in &file.recipe {
+            let in_recipe = |e: String| format!("In the recipe for {}: {}", entry.result, e);
+            if entry.count == 0 || entry.ingredients.iter().any(|i| i.count == 0) {
+                return Err(in_recipe("counts have to be at least 1".to_string()));
+            }
+            let result = ItemStack::new(item(&entry.result).map_err(in_recipe)?, entry.count);
+            // the same item listed twice is one bigger ingredient
+            let mut ingredients: Vec<ItemStack> = Vec::new();
+            for ingredient in &entry.ingredients {
+                let id = item(&ingredient.item).map_err(in_recipe)?;
+                match ingredients.iter_mut().find(|s| s.get_id() == id) {
+                    Some(s) => *s = ItemStack::new(id, s.get_count() + ingredient.count),
+                    None => ingredients.push(ItemStack::new(id, ingredient.count)),
+                }
+            }
+            if ingredients.is_empty() {
+                return Err(in_recipe("there are no ingredients".to_string()));
+            }
+            let station = match &entry.station {
+                Some(name) => Some(TILE_REGISTRY.id(name).ok_or_else(|| in_recipe(format!("Unknown tile {}", name)))?),
+                None => None,
+            };
+            book.add(Recipe { result, ingredients, station });
+        }
+        Ok(book)
+    }
+    fn add(&mut self, recipe: Recipe) {
+        let index = self.recipes.len();
+        for ingredient in &recipe.ingredients {
+            self.by_ingredient.entry(ingredient.get_id()).or_default().push(index);
+        }
+        if let Some(station) = recipe.station {
+            self.stations.insert(station);
+        }
+        self.recipes.push(recipe);
+    }
+    pub fn get(&self, index: usize) -> Option<&Recipe> {
+        self.recipes.get(index)
+    }
+    // indices of the recipes that use `id`
+    pub fn uses_of(&self, id: ItemId) -> &[usize] {
+        self.by_ingredient.get(&id).map_or(&[], |r| r.as_slice())
+    }
+    // indices of every recipe that can be made from `inventory` next to `stations`, in file order
+    pub fn craftable(&self, inventory: &Inventory, stations: &HashSet<TileId>) -> Vec<usize> {
+        let counts = inventory.counts();
+        let mut candidates: Vec<usize> = counts.keys().flat_map(|&id| self.uses_of(id).iter().copied()).collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates.retain(|&i| self.can_craft(&self.recipes[i], &counts, stations));
+        candidates
+    }
+    fn can_craft(&self, recipe: &Recipe, counts: &HashMap<ItemId, u32>, stations: &HashSet<TileId>) -> bool {
+        recipe.station.is_none_or(|s| stations.contains(&s))
+            && recipe.ingredients.iter().all(|i| counts.get(&i.get_id()).copied().unwrap_or(0) >= i.get_count())
+    }
+    // makes a recipe once: the ingredients are used up and the result added, or, if anything is
+    // missing or the result does not fit, the inventory is left as it was
+    pub fn craft(&self, index: usize, inventory: &mut Inventory, stations: &HashSet<TileId>) -> Result<(), String> {
+        let recipe = self.get(index).ok_or_else(|| format!("There is no recipe {}", index))?;
+        let name = &recipe.result.get_id().info().name;
+        if !self.can_craft(recipe, &inventory.counts(), stations) {
+            return Err(format!("The recipe for {} is missing an ingredient or station", name));
+        }
+        let mut after = inventory.clone();
+        for ingredient in &recipe.ingredients {
+            after.remove(ingredient.get_id(), ingredient.get_count());
+        }
+        if after.add(recipe.result).is_some() {
+            return Err(format!("There is no room for {}", name));
+        }
+        *inventory = after;
+        Ok(())
+    }
+    // the station tiles within reach of `body`
+    pub fn nearby_stations(&self, world: &World, body: &Body) -> HashSet<TileId> {
+        let mut found = HashSet::new();
+        if self.stations.is_empty() {
+            return found;
+        }
+        let left = (body.x.floor() as i64 - STATION_RANGE_X).max(0);
+        let right = ((body.x + body.w).ceil() as i64 + STATION_RANGE_X).min(world.width() as i64);
+        let top = (body.y.floor() as i64 - STATION_RANGE_Y).max(0);
+        let bottom = (body.bottom().ceil() as i64 + STATION_RANGE_Y).min(world.height() as i64);
+        for (_, _, tile) in world.tiles_in(left as usize, top as usize, (right - left).max(0) as usize,
+                                            (bottom - top).max(0) as usize) {
+            if self.stations.contains(&tile.get_id()) {
+                found.insert(tile.get_id());
+            }
+        }
+        found
+    }
+}
@@ -0,0 +1,129 @@
+use sdl2::render::{Canvas, RenderTarget};
+use crate::common::Size;
+use crate::entity::Body;
+use crate::item::{Inventory, ItemAtlases, ItemStack};
+use crate::tile::TILE_SIZE;
+use crate::viewport::Viewport;
+use crate::world::World;
+
+// the hitbox of an item lying in the world, in tiles
+const SIZE: f64 = 0.75;
+// items closer than this to the player's center get pulled in, and closer than PICKUP_RADIUS
+// they are picked up; in tiles
+const MAGNET_RADIUS: f64 = 5.0;
+const PICKUP_RADIUS: f64 = 1.25;
+// how hard the pull is and how fast it can get, in tiles per second (squared)
+const MAGNET_ACCEL: f64 = 120.0;
+const MAGNET_SPEED: f64 = 18.0;
+// slows items sliding along the ground, in tiles per second squared
+const FRICTION: f64 = 30.0;
+// items of the same kind closer than this merge into one
+const MERGE_RADIUS: f64 = 0.5;
+
+// a stack lying in the world
+pub struct DroppedItem {
+    body: Body,
+    prev_pos: (f64, f64),
+    stack: ItemStack,
+    // all of it went into an inventory; removed at the end of the tick
+    picked_up: bool,
+}
+
+// every item lying in the world, falling, sliding and being pulled towards whoever can pick it up
+pub struct DroppedItems {
+    items: Vec<DroppedItem>,
+}
+
+impl DroppedItems {
+    pub fn new() -> DroppedItems {
+        DroppedItems { items: Vec::new() }
+    }
+    // drops `stack` centered on (x, y) in tile coordinates, with a little pop upwards
+    pub fn spawn(&mut self, x: f64, y: f64, stack: ItemStack) {
+        let mut body = Body::new(x - SIZE / 2.0, y - SIZE / 2.0, SIZE, SIZE);
+        body.vy = -6.0;
+        self.items.push(DroppedItem { prev_pos: (body.x, body.y), body, stack, picked_up: false });
+    }
+    // advances every item by `dt` seconds; items near `collector` that fit in `inventory` are
+    // pulled in and picked up
+    pub fn update(&mut self, world: &World, dt: f64, collector: &Body, inventory: &mut Inventory) {
+        let (px, py) = collector.center();
+        for item in &mut self.items {
+            item.prev_pos = (item.body.x, item.body.y);
+            let (ix, iy) = item.body.center();
+            let (dx, dy) = (px - ix, py - iy);
+            let distance = (dx * dx + dy * dy).sqrt();
+            if distance < MAGNET_RADIUS && inventory.can_accept(item.stack.get_id()) {
+                if distance < PICKUP_RADIUS {
+                    match inventory.add(item.stack) {
+                        Some(left) => item.stack = left,
+                        None => {
+                            item.picked_up = true;
+                            continue;
+                        }
+                    }
+                }
+                let body = &mut item.body;
+                body.vx += dx / distance.max(1e-6) * MAGNET_ACCEL * dt;
+                body.vy += dy / distance.max(1e-6) * MAGNET_ACCEL * dt;
+                let speed = (body.vx * body.vx + body.vy * body.vy).sqrt();
+                if speed > MAGNET_SPEED {
+                    body.vx *= MAGNET_SPEED / speed;
+                    body.vy *= MAGNET_SPEED / speed;
+                }
+            } else {
+                let body = &mut item.body;
+                body.apply_gravity(dt);
+                if body.get_on_ground() {
+                    let slowed = body.vx.abs() - FRICTION * dt;
+                    body.vx = body.vx.signum() * slowed.max(0.0);
+                }
+            }
+            item.body.move_and_collide(world, dt, false);
+        }
+        self.items.retain(|item| !item.picked_up);
+        self.merge_neighbors();
+    }
+    // stacks that come to rest on each other become one, so mining a tunnel does not leave
+    // hundreds of single items behind
+    fn merge_neighbors(&mut self) {
+        let mut i = 0;
+        while i < self.items.len() {
+            let (ix, iy) = self.items[i].body.center();
+            let mut j = i + 1;
+            while j < self.items.len() {
+                let (jx, jy) = self.items[j].body.center();
+                let (a, b) = (self.items[i].stack, self.items[j].stack);
+                let total = a.get_count() + b.get_count();
+                if a.get_id() == b.get_id() && (ix - jx).abs() < MERGE_RADIUS && (iy - jy).abs() < MERGE_RADIUS
+                    && total <= a.get_id().info().max_stack {
+                    self.items[i].stack = ItemStack::new(a.get_id(), total);
+                    self.items.swap_remove(j);
+                } else {
+                    j += 1;
+                }
+            }
+            i += 1;
+        }
+    }
+    pub fn render<T: RenderTarget>(&self, atlases: &ItemAtlases, canvas: &mut Canvas<T>, view: &Viewport,
+                                   alpha: f64) -> Result<(), String> {
+        let scale = view.tile_pixels() / f64::from(TILE_SIZE);
+        for item in &self.items {
+            let sprite = match atlases.get(&item.stack.get_id()) {
+                Some(s) => s.get(),
+                None => continue,
+            };
+            // sprites keep their own size, standing on the bottom of the hitbox
+            let x = item.prev_pos.0 + (item.body.x - item.prev_pos.0) * alpha + SIZE / 2.0;
+            let y = item.prev_pos.1 + (item.body.y - item.prev_pos.1) * alpha + SIZE;
+            let (cx, bottom) = view.point_to_screen(x, y);
+            let size = Size {
+                w: (f64::from(sprite.width()) * scale).round() as u32,
+                h: (f64::from(sprite.height()) * scale).round() as u32,
+            };
+            sprite.render(canvas, cx - size.w as i32 / 2, bottom - size.h as i32, Some(size), None)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+use crate::tile::TileShape;
+use crate::world::World;
+
+// in tiles per second squared
+pub const GRAVITY: f64 = 60.0;
+// fastest anything falls, in tiles per second
+pub const TERMINAL_VELOCITY: f64 = 40.0;
+// highest ledge a body walks up without jumping, in tiles
+const STEP_HEIGHT: f64 = 1.0;
+// how far a body that was standing looks down for ground to stay on, so it follows slopes down
+// instead of hopping off them
+const SNAP_DISTANCE: f64 = 0.5;
+const EPSILON: f64 = 1e-6;
+
+// an axis-aligned box that moves through the world and collides with its solid tiles; anything
+// that walks, falls or flies is built around one. All units are tiles, with y pointing down
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Body {
+    // top left corner
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+    pub vx: f64,
+    pub vy: f64,
+    on_ground: bool,
+}
+
+impl Body {
+    pub fn new(x: f64, y: f64, w: f64, h: f64) -> Body {
+        Body {
+            x,
+            y,
+            w,
+            h,
+            vx: 0.0,
+            vy: 0.0,
+            on_ground: false,
+        }
+    }
+    pub fn get_on_ground(&self) -> bool {
+        self.on_ground
+    }
+    pub fn center(&self) -> (f64, f64) {
+        (self.x + self.w / 2.0, self.y + self.h / 2.0)
+    }
+    pub fn bottom(&self) -> f64 {
+        self.y + self.h
+    }
+    pub fn overlaps(&self, other: &Body) -> bool {
+        self.x < other.x + other.w && other.x < self.x + self.w && self.y < other.y + other.h && other.y < self.y + self.h
+    }
+    // whether any solid tile pokes into the body, as when something burrows
+    pub fn in_solid(&self, world: &World) -> bool {
+        overlaps_solid(world, self.x, self.y, self.w, self.h)
+    }
+    pub fn apply_gravity(&mut self, dt: f64) {
+        self.vy = (self.vy + GRAVITY * dt).min(TERMINAL_VELOCITY);
+    }
+    // moves by the velocity over `dt` seconds, one axis at a time, stopping at solid tiles. With
+    // `step_up` a body on the ground walks up ledges of up to a tile instead of stopping at them
+    pub fn move_and_collide(&mut self, world: &World, dt: f64, step_up: bool) {
+        let was_on_ground = self.on_ground;
+        self.move_x(world, self.vx * dt, step_up && was_on_ground);
+        self.move_y(world, self.vy * dt, was_on_ground);
+    }
+    fn move_x(&mut self, world: &World, dx: f64, step_up: bool) {
+        if dx == 0.0 {
+            return;
+        }
+        let (rows_top, rows_bottom) = (self.y.floor() as i64, (self.bottom() - EPSILON).floor() as i64);
+        // columns the leading edge passes into, nearest first, and which side of them it enters
+        let (columns, entry): (Vec<i64>, f64) = if dx > 0.0 {
+            let edge = self.x + self.w;
+            (((edge - EPSILON).floor() as i64 + 1..=(edge + dx - EPSILON).floor() as i64).collect(), 0.0)
+        } else {
+            ((((self.x + dx).floor() as i64)..=((self.x + EPSILON).floor() as i64 - 1)).rev().collect(), 1.0)
+        };
+        for i in columns {
+            // the highest point of any tile in this column that is in the way
+            let mut blocking_top: Option<f64> = None;
+            for j in rows_top..=rows_bottom {
+                if let Some(shape) = solid_shape(world, i, j) {
+                    let top = j as f64 + 1.0 - shape.height_at(entry);
+                    if self.bottom() > top + EPSILON && self.y < j as f64 + 1.0 - EPSILON {
+                        blocking_top = Some(blocking_top.map_or(top, |b: f64| b.min(top)));
+                    }
+                }
+            }
+            if let Some(top) = blocking_top {
+                let rise = self.bottom() - top;
+                let wall_x = if dx > 0.0 { i as f64 - self.w } else { i as f64 + 1.0 };
+                if step_up && rise <= STEP_HEIGHT + EPSILON && !overlaps_solid(world, self.x.min(wall_x),
+                        top - self.h, self.w + (wall_x - self.x).abs(), self.h) {
+                    // climb the ledge and keep going
+                    self.y = top - self.h;
+                    self.move_x(world, dx, true);
+                } else {
+                    self.x = wall_x;
+                    self.vx = 0.0;
+                }
+                return;
+            }
+        }
+        self.x += dx;
+    }
+    fn move_y(&mut self, world: &World, dy: f64, was_on_ground: bool) {
+        if dy < 0.0 {
+            self.on_ground = false;
+            let rows = ((self.y + dy).floor() as i64..=((self.y + EPSILON).floor() as i64 - 1)).rev();
+            for j in rows {
+                if self.columns().any(|i| solid_shape(world, i, j).is_some()) {
+                    // every shape has a flat bottom
+                    self.y = j as f64 + 1.0;
+                    self.vy = 0.0;
+                    return;
+                }
+            }
+            self.y += dy;
+            return;
+        }
+        // stick to the ground when walking down slopes, unless something threw the body upwards
+        let reach = if was_on_ground { dy.max(SNAP_DISTANCE) } else { dy };
+        match self.floor_within(world, reach) {
+            Some(top) => {
+                self.y = top - self.h;
+                self.vy = 0.0;
+                self.on_ground = true;
+            }
+            None => {
+                self.y += dy;
+                self.on_ground = false;
+            }
+        }
+    }
+    // the highest ground under the body from a little above its feet (so that it can be pushed
+    // up out of slopes and half blocks it has walked into) to `reach` below them
+    fn floor_within(&self, world: &World, reach: f64) -> Option<f64> {
+        let bottom = self.bottom();
+        let (x0, x1) = (self.x, self.x + self.w);
+        let mut floor: Option<f64> = None;
+        for j in (bottom - STEP_HEIGHT).floor() as i64..=(bottom + reach + EPSILON).floor() as i64 {
+            for i in self.columns() {
+                let shape = match solid_shape(world, i, j) {
+                    Some(s) => s,
+                    None => continue,
+                };
+                let top = surface_top(shape, i, j, x0, x1);
+                // full tiles above the feet are walls, which `move_x` already dealt with
+                let lowest_allowed = if shape == TileShape::Full { bottom - EPSILON } else { bottom - STEP_HEIGHT };
+                if top >= lowest_allowed && top <= bottom + reach + EPSILON {
+                    floor = Some(floor.map_or(top, |f: f64| f.min(top)));
+                }
+            }
+        }
+        floor
+    }
+    fn columns(&self) -> impl Iterator<Item=i64> {
+        self.x.floor() as i64..=(self.x + self.w - EPSILON).floor() as i64
+    }
+}
+
+// the shape of the solid tile at (i, j), if there is one; the sides and bottom of the world are
+// walls and the sky above it is open
+fn solid_shape(world: &World, i: i64, j: i64) -> Option<TileShape> {
+    if j < 0 {
+        return None;
+    }
+    if i < 0 || i >= world.width() as i64 || j >= world.height() as i64 {
+        return Some(TileShape::Full);
+    }
+    let t = world.get_tile(i as usize, j as usize).unwrap();
+    if t.get_solid() {
+        Some(t.get_shape())
+    } else {
+        None
+    }
+}
+
+// the highest point of a tile at (i, j) anywhere between x0 and x1
+fn surface_top(shape: TileShape, i: i64, j: i64, x0: f64, x1: f64) -> f64 {
+    let lo = (x0 - i as f64).clamp(0.0, 1.0);
+    let hi = (x1 - i as f64).clamp(0.0, 1.0);
+    j as f64 + 1.0 - shape.height_at(lo).max(shape.height_at(hi))
+}
+
+// whether a box overlaps the solid part of any tile
+fn overlaps_solid(world: &World, x: f64, y: f64, w: f64, h: f64) -> bool {
+    for j in y.floor() as i64..=(y + h - EPSILON).floor() as i64 {
+        for i in x.floor() as i64..=(x + w - EPSILON).floor() as i64 {
+            if let Some(shape) = solid_shape(world, i, j) {
+                if y + h > surface_top(shape, i, j, x, x + w) + EPSILON && y < j as f64 + 1.0 - EPSILON {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
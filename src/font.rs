@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, RenderTarget, TextureCreator};
+use sdl2::surface::Surface;
+use crate::common::Size;
+use crate::sprite::Sprite;
+
+pub const DEFAULT_FONT: &str = "sprites/Font_Mouse_Text.fnt";
+
+// the built-in font covers printable ascii; each glyph is 5 columns of 7 pixels, bit 0 at the top
+const FIRST_CHAR: u8 = b' ';
+const GLYPH_W: u32 = 5;
+const GLYPH_H: u32 = 7;
+const BUILTIN_GLYPHS: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00], [0x00, 0x1C, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1C, 0x00], [0x14, 0x08, 0x3E, 0x08, 0x14], [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02], [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4B, 0x31], [0x18, 0x14, 0x12, 0x7F, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39], [0x3C, 0x4A, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1E], [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00], [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06], [0x32, 0x49, 0x79, 0x41, 0x3E],
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], [0x7F, 0x49, 0x49, 0x49, 0x41], [0x7F, 0x09, 0x09, 0x01, 0x01],
+    [0x3E, 0x41, 0x41, 0x51, 0x32], [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41], [0x7F, 0x40, 0x40, 0x40, 0x40],
+    [0x7F, 0x02, 0x04, 0x02, 0x7F], [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], [0x7F, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31], [0x01, 0x01, 0x7F, 0x01, 0x01], [0x3F, 0x40, 0x40, 0x40, 0x3F],
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x7F, 0x20, 0x18, 0x20, 0x7F], [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x03, 0x04, 0x78, 0x04, 0x03], [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7F, 0x00], [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40], [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7F, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20], [0x38, 0x44, 0x44, 0x48, 0x7F],
+    [0x38, 0x54, 0x54, 0x54, 0x18], [0x08, 0x7E, 0x09, 0x01, 0x02], [0x08, 0x54, 0x54, 0x54, 0x3C],
+    [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], [0x20, 0x40, 0x44, 0x3D, 0x00],
+    [0x7F, 0x10, 0x28, 0x44, 0x00], [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x18, 0x04, 0x78],
+    [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38], [0x7C, 0x14, 0x14, 0x14, 0x08],
+    [0x08, 0x14, 0x14, 0x18, 0x7C], [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3F, 0x44, 0x40, 0x20], [0x3C, 0x40, 0x40, 0x20, 0x7C], [0x1C, 0x20, 0x40, 0x20, 0x1C],
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], [0x44, 0x28, 0x10, 0x28, 0x44], [0x0C, 0x50, 0x50, 0x50, 0x3C],
+    [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00], [0x00, 0x00, 0x7F, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00], [0x08, 0x04, 0x08, 0x10, 0x08],
+];
+// width of a space, which has no pixels to measure, and the gap after every glyph; in font pixels
+const SPACE_W: u32 = 3;
+const GLYPH_GAP: u32 = 1;
+
+// laid out texts kept per style; past this many the cache starts over, so text that changes every
+// frame cannot grow it forever
+const LAYOUT_CACHE_SIZE: usize = 512;
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+#[derive(Hash)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+// how a text is drawn; lines are split on '\n' and, with `wrap`, between words so no line is wider
+// than that many screen pixels; lines are aligned within the widest line, or within `wrap`
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct TextStyle {
+    pub color: Color,
+    pub align: Align,
+    pub wrap: Option<u32>,
+}
+
+impl TextStyle {
+    pub fn new(color: Color) -> TextStyle {
+        TextStyle { color, align: Align::Left, wrap: None }
+    }
+}
+
+// where a character is in the font's pages, where it goes relative to the pen and how far the pen
+// moves past it; in font pixels
+#[derive(Clone)]
+#[derive(Copy)]
+struct Glyph {
+    page: usize,
+    // `None` for characters with nothing to draw, like spaces
+    source: Option<Rect>,
+    offset: (i32, i32),
+    advance: i32,
+}
+
+// a glyph put in place by a layout, relative to the top left of the text; in font pixels
+struct Quad {
+    page: usize,
+    source: Rect,
+    x: i32,
+    y: i32,
+}
+
+// a text turned into quads once, so drawing it again is only copies
+struct Layout {
+    quads: Vec<Quad>,
+    w: u32,
+    h: u32,
+}
+
+// glyphs in one or more textures; text is drawn by copying glyphs out of them, tinted and scaled
+// up by a whole number so the pixels stay sharp
+pub struct Font<'a> {
+    pages: Vec<Sprite<'a>>,
+    glyphs: HashMap<char, Glyph>,
+    // extra pen movement between two characters
+    kerning: HashMap<(char, char), i32>,
+    line_height: u32,
+    scale: u32,
+    // keyed by alignment and wrap width in font pixels, then by text
+    layouts: HashMap<(Align, Option<u32>), HashMap<String, Rc<Layout>>>,
+    cached: usize,
+}
+
+impl<'a> Font<'a> {
+    // the font compiled into the game, so there is always something to draw text with
+    pub fn builtin<C>(texture_creator: &'a TextureCreator<C>) -> Result<Font<'a>, String> {
+        let cell_w = GLYPH_W + GLYPH_GAP;
+        let mut surface = Surface::new(cell_w * BUILTIN_GLYPHS.len() as u32, GLYPH_H, PixelFormatEnum::RGBA32)?;
+        let pitch = surface.pitch() as usize;
+        let mut glyphs = HashMap::new();
+        surface.with_lock_mut(|pixels| {
+            for (i, columns) in BUILTIN_GLYPHS.iter().enumerate() {
+                for (col, bits) in columns.iter().enumerate() {
+                    for row in (0..GLYPH_H as usize).filter(|r| bits & (1 << r) != 0) {
+                        let at = row * pitch + (i * cell_w as usize + col) * 4;
+                        pixels[at..at + 4].copy_from_slice(&[255, 255, 255, 255]);
+                    }
+                }
+                // glyphs are as wide as their pixels, so "il" is not spaced like "mw"
+                let first = columns.iter().position(|&c| c != 0);
+                let last = columns.iter().rposition(|&c| c != 0);
+                let (source, width) = match (first, last) {
+                    (Some(f), Some(l)) => {
+                        let width = (l - f + 1) as u32;
+                        (Some(Rect::new((i as u32 * cell_w) as i32 + f as i32, 0, width, GLYPH_H)), width)
+                    }
+                    _ => (None, SPACE_W),
+                };
+                let advance = (width + GLYPH_GAP) as i32;
+                glyphs.insert(char::from(FIRST_CHAR + i as u8), Glyph { page: 0, source, offset: (0, 0), advance });
+            }
+        });
+        let mut page = Sprite::new();
+        page.load_from_surface(texture_creator, surface)?;
+        Ok(Font::with_pages(vec![page], glyphs, HashMap::new(), GLYPH_H + 2))
+    }
+    // reads a font in the BMFont text format; its pages are found next to it
+    pub fn load_from_file<C>(texture_creator: &'a TextureCreator<C>, path: &str) -> Result<Font<'a>, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        let in_file = |e: String| format!("{}: {}", path, e);
+        let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        let mut page_files: Vec<(usize, String)> = Vec::new();
+        let mut glyphs = HashMap::new();
+        let mut kerning = HashMap::new();
+        let mut line_height = None;
+        for (number, line) in text.lines().enumerate() {
+            let in_line = |e: String| in_file(format!("line {}: {}", number + 1, e));
+            let (tag, values) = parse_line(line);
+            let get = |key: &str| -> Result<i32, String> {
+                let value = values.get(key).ok_or_else(|| in_line(format!("{} is missing {}", tag, key)))?;
+                value.parse().map_err(|_| in_line(format!("{} is not a number: {}", key, value)))
+            };
+            match tag {
+                "common" => line_height = Some(get("lineHeight")?.max(1) as u32),
+                "page" => {
+                    let file = values.get("file").ok_or_else(|| in_line("page is missing file".to_string()))?;
+                    page_files.push((get("id")? as usize, file.to_string()));
+                }
+                "char" => {
+                    let id = char_from(get("id")?).ok_or_else(|| in_line("char id is not a character".to_string()))?;
+                    let (w, h) = (get("width")?, get("height")?);
+                    let source = if w > 0 && h > 0 { Some(Rect::new(get("x")?, get("y")?, w as u32, h as u32)) } else { None };
+                    let page = values.get("page").map_or(Ok(0), |_| get("page"))? as usize;
+                    glyphs.insert(id, Glyph {
+                        page,
+                        source,
+                        offset: (get("xoffset")?, get("yoffset")?),
+                        advance: get("xadvance")?,
+                    });
+                }
+                "kerning" => {
+                    let pair = char_from(get("first")?).zip(char_from(get("second")?))
+                        .ok_or_else(|| in_line("kerning is not between characters".to_string()))?;
+                    kerning.insert(pair, get("amount")?);
+                }
+                _ => {}
+            }
+        }
+        let line_height = line_height.ok_or_else(|| in_file("there is no common line".to_string()))?;
+        page_files.sort();
+        let mut pages = Vec::new();
+        for (i, (id, file)) in page_files.iter().enumerate() {
+            if *id != i {
+                return Err(in_file(format!("page {} is missing", i)));
+            }
+            let mut page = Sprite::new();
+            page.load_from_file(texture_creator, &dir.join(file).to_string_lossy(), None).map_err(in_file)?;
+            pages.push(page);
+        }
+        if let Some(c) = glyphs.iter().find(|(_, g)| g.page >= pages.len()).map(|(c, _)| c) {
+            return Err(in_file(format!("character {:?} is on a page that does not exist", c)));
+        }
+        Ok(Font::with_pages(pages, glyphs, kerning, line_height))
+    }
+    fn with_pages(pages: Vec<Sprite<'a>>, glyphs: HashMap<char, Glyph>, kerning: HashMap<(char, char), i32>,
+                  line_height: u32) -> Font<'a> {
+        let mut pages = pages;
+        for page in &mut pages {
+            page.set_blend_mode(BlendMode::Blend);
+        }
+        Font {
+            pages,
+            glyphs,
+            kerning,
+            line_height,
+            scale: 1,
+            layouts: HashMap::new(),
+            cached: 0,
+        }
+    }
+    // how many screen pixels each font pixel covers
+    pub fn set_scale(&mut self, scale: u32) {
+        self.scale = scale.max(1);
+    }
+    // the size of `text` when drawn unwrapped, in screen pixels
+    pub fn measure(&mut self, text: &str) -> Size {
+        self.measure_wrapped(text, None)
+    }
+    // the size of `text` when wrapped to `wrap` screen pixels
+    pub fn measure_wrapped(&mut self, text: &str, wrap: Option<u32>) -> Size {
+        let layout = self.layout(text, Align::Left, wrap);
+        Size { w: layout.w * self.scale, h: layout.h * self.scale }
+    }
+    // draws `text` left aligned with its top left corner at (x, y)
+    pub fn render<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, x: i32, y: i32, text: &str,
+                                   color: Color) -> Result<(), String> {
+        self.render_styled(canvas, x, y, text, &TextStyle::new(color))
+    }
+    // draws `text` with the top left corner of its block at (x, y)
+    pub fn render_styled<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, x: i32, y: i32, text: &str,
+                                          style: &TextStyle) -> Result<(), String> {
+        let layout = self.layout(text, style.align, style.wrap);
+        for page in &mut self.pages {
+            page.set_color_mod(style.color);
+            page.set_alpha(style.color.a);
+        }
+        let scale = self.scale;
+        for quad in &layout.quads {
+            let size = Size { w: quad.source.width() * scale, h: quad.source.height() * scale };
+            self.pages[quad.page].render(canvas, x + quad.x * scale as i32, y + quad.y * scale as i32, Some(size),
+                                         Some(quad.source))?;
+        }
+        Ok(())
+    }
+    // the cached layout of `text`, made on first use
+    fn layout(&mut self, text: &str, align: Align, wrap: Option<u32>) -> Rc<Layout> {
+        let key = (align, wrap.map(|w| w / self.scale));
+        if let Some(layout) = self.layouts.get(&key).and_then(|texts| texts.get(text)) {
+            return layout.clone();
+        }
+        let layout = Rc::new(self.make_layout(text, key.0, key.1));
+        if self.cached >= LAYOUT_CACHE_SIZE {
+            self.layouts.clear();
+            self.cached = 0;
+        }
+        self.layouts.entry(key).or_default().insert(text.to_string(), layout.clone());
+        self.cached += 1;
+        layout
+    }
+    fn make_layout(&self, text: &str, align: Align, wrap: Option<u32>) -> Layout {
+        let mut lines: Vec<String> = Vec::new();
+        for paragraph in text.split('\n') {
+            let mut line = String::new();
+            // words longer than the wrap width get a line of their own and stick out of it
+            for word in paragraph.split(' ') {
+                let joined = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+                if !line.is_empty() && wrap.is_some_and(|w| self.place_line(&joined).1 > w) {
+                    lines.push(std::mem::replace(&mut line, word.to_string()));
+                } else {
+                    line = joined;
+                }
+            }
+            lines.push(line);
+        }
+        let placed: Vec<(Vec<(Glyph, i32)>, u32)> = lines.iter().map(|l| self.place_line(l)).collect();
+        let widest = placed.iter().map(|(_, w)| *w).max().unwrap_or(0);
+        let w = wrap.unwrap_or(widest);
+        let mut quads = Vec::new();
+        for (row, (glyphs, line_w)) in placed.iter().enumerate() {
+            let shift = match align {
+                Align::Left => 0,
+                Align::Right => w as i32 - *line_w as i32,
+            };
+            let top = (row as u32 * self.line_height) as i32;
+            for (glyph, pen) in glyphs {
+                let source = match glyph.source {
+                    Some(s) => s,
+                    None => continue,
+                };
+                quads.push(Quad {
+                    page: glyph.page,
+                    source,
+                    x: shift + pen + glyph.offset.0,
+                    y: top + glyph.offset.1,
+                });
+            }
+        }
+        Layout { quads, w, h: lines.len() as u32 * self.line_height }
+    }
+    // the glyphs of a line with the pen position each is drawn at, and how far right the line's
+    // pixels reach; characters the font lacks are drawn as '?'
+    fn place_line(&self, line: &str) -> (Vec<(Glyph, i32)>, u32) {
+        let mut placed = Vec::new();
+        let mut pen = 0;
+        let mut right = 0;
+        let mut prev = None;
+        for c in line.chars() {
+            let glyph = match self.glyphs.get(&c).or_else(|| self.glyphs.get(&'?')) {
+                Some(g) => *g,
+                None => continue,
+            };
+            if let Some(p) = prev {
+                pen += self.kerning.get(&(p, c)).copied().unwrap_or(0);
+            }
+            if let Some(source) = glyph.source {
+                right = right.max(pen + glyph.offset.0 + source.width() as i32);
+                placed.push((glyph, pen));
+            }
+            pen += glyph.advance;
+            prev = Some(c);
+        }
+        (placed, right.max(0) as u32)
+    }
+}
+
+fn char_from(code: i32) -> Option<char> {
+    u32::try_from(code).ok().and_then(char::from_u32)
+}
+
+// splits a line like `page id=0 file="a b.png"` into its tag and its values, without the quotes
+fn parse_line(line: &str) -> (&str, HashMap<&str, &str>) {
+    let line = line.trim();
+    let (tag, mut rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let mut values = HashMap::new();
+    loop {
+        rest = rest.trim_start();
+        let (key, after) = match rest.split_once('=') {
+            Some(kv) => kv,
+            None => break,
+        };
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(char::is_whitespace).unwrap_or((after, "")),
+        };
+        values.insert(key.trim(), value);
+        rest = after;
+    }
+    (tag, values)
+}
@@ -0,0 +1,108 @@
+use std::time::{Duration, Instant};
+
+// a frame that took longer than this is treated as if it took this long; past it the simulation
+// slows down instead of trying to catch up, since catching up makes the next frame take even
+// longer (the spiral of death)
+const MAX_FRAME_TIME: Duration = Duration::from_millis(250);
+
+// the hooks the loop drives; `update` always advances the simulation by the same fixed amount
+pub trait GameState {
+    // called once at the start of every frame, before any ticks; returning false ends the loop
+    fn begin_frame(&mut self) -> bool;
+    // advances the simulation by `dt` seconds
+    fn update(&mut self, dt: f64);
+    // draws the state `alpha` of the way (0 to 1) from the tick before the last one to the last
+    // one, so that motion looks smooth when frames and ticks do not line up
+    fn render(&mut self, alpha: f64) -> Result<(), String>;
+}
+
+// runs a `GameState` with fixed-length simulation ticks and rendering as often as the frame cap
+// allows; time left over between frames carries into the next one
+pub struct GameLoop {
+    tick: Duration,
+    // shortest time a frame may take; `None` renders as fast as possible
+    frame_cap: Option<Duration>,
+    // simulation time that has passed but not been ticked yet
+    accumulator: Duration,
+    // statistics
+    frames: u64,
+    ticks: u64,
+    total_frame_time: Duration,
+    slow_frames: u64,
+}
+
+impl GameLoop {
+    pub fn new(ticks_per_second: u32) -> GameLoop {
+        GameLoop {
+            tick: Duration::from_secs(1) / ticks_per_second,
+            frame_cap: None,
+            accumulator: Duration::from_secs(0),
+            frames: 0,
+            ticks: 0,
+            total_frame_time: Duration::from_secs(0),
+            slow_frames: 0,
+        }
+    }
+    pub fn set_frame_cap(&mut self, frame_cap: Option<Duration>) {
+        self.frame_cap = frame_cap;
+    }
+    // length of one tick in seconds
+    pub fn get_dt(&self) -> f64 {
+        self.tick.as_secs_f64()
+    }
+    pub fn get_frames(&self) -> u64 {
+        self.frames
+    }
+    pub fn get_ticks(&self) -> u64 {
+        self.ticks
+    }
+    // frames that took longer than the frame cap
+    pub fn get_slow_frames(&self) -> u64 {
+        self.slow_frames
+    }
+    pub fn average_frame_time(&self) -> Duration {
+        if self.frames == 0 {
+            Duration::from_secs(0)
+        } else {
+            self.total_frame_time / self.frames as u32
+        }
+    }
+    // adds `elapsed` real time to the accumulator and returns how many ticks are due and the
+    // render alpha that is left over
+    pub fn advance(&mut self, elapsed: Duration) -> (u32, f64) {
+        self.accumulator += elapsed.min(MAX_FRAME_TIME);
+        let mut ticks = 0;
+        while self.accumulator >= self.tick {
+            self.accumulator -= self.tick;
+            ticks += 1;
+        }
+        (ticks, self.accumulator.as_secs_f64() / self.tick.as_secs_f64())
+    }
+    pub fn run<S: GameState>(&mut self, state: &mut S) -> Result<(), String> {
+        let dt = self.get_dt();
+        let mut last_frame = Instant::now();
+        loop {
+            let frame_start = Instant::now();
+            if !state.begin_frame() {
+                return Ok(());
+            }
+            let (ticks, alpha) = self.advance(frame_start - last_frame);
+            last_frame = frame_start;
+            for _ in 0..ticks {
+                state.update(dt);
+            }
+            self.ticks += u64::from(ticks);
+            state.render(alpha)?;
+            let frame_time = frame_start.elapsed();
+            self.frames += 1;
+            self.total_frame_time += frame_time;
+            if let Some(cap) = self.frame_cap {
+                if frame_time < cap {
+                    std::thread::sleep(cap - frame_time);
+                } else {
+                    self.slow_frames += 1;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::fs;
+use std::path::Path;
+use sdl2::image::SaveSurface;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::render::{Canvas, RenderTarget, SurfaceCanvas};
+use sdl2::surface::Surface;
+use crate::assets::Assets;
+use crate::atlas::Atlas;
+use crate::common::Size;
+use crate::lighting::{LightOverlay, Lighting};
+use crate::liquid::{self, LiquidSim};
+use crate::character::{Appearance, Character, Equipment};
+use crate::player::Player;
+use crate::tile;
+use crate::viewport::Viewport;
+use crate::wall;
+use crate::world::World;
+
+// an offscreen canvas drawn by SDL's software renderer; needs no window or display, and takes
+// everything that draws to a window canvas
+pub fn create_canvas(size: Size) -> Result<SurfaceCanvas<'static>, String> {
+    let surface = Surface::new(size.w, size.h, PixelFormatEnum::ARGB8888)?;
+    surface.into_canvas()
+}
+
+// writes whatever is on `canvas` right now to a PNG; works for window canvases too
+pub fn save_png<T: RenderTarget>(canvas: &Canvas<T>, path: &str) -> Result<(), String> {
+    let (w, h) = canvas.output_size()?;
+    let mut pixels = canvas.read_pixels(None, PixelFormatEnum::ARGB8888)?;
+    let surface = Surface::from_data(&mut pixels, w, h, w * 4, PixelFormatEnum::ARGB8888)?;
+    surface.save(path).map_err(|e| format!("Could not write {}: {}", path, e))
+}
+
+// renders `frames` frames of the scene the window would show into `out_dir/frame_NNNN.png`,
+// advancing the liquid between frames; lighting is waited on every frame so that the same world
+// always gives the same images
+pub fn run(world: &mut World, view: &mut Viewport, player: &Player, size: Size, frames: u32, out_dir: &str) -> Result<(), String> {
+    fs::create_dir_all(out_dir).map_err(|e| format!("Could not create {}: {}", out_dir, e))?;
+    view.set_output_dimensions(size);
+    let mut canvas = create_canvas(size)?;
+    let texture_creator = canvas.texture_creator();
+    let mut assets = Assets::new(&texture_creator, Atlas::load_packed(&texture_creator))?;
+    let tile_atlases = tile::load_tile_atlases(&mut assets)?;
+    let wall_atlases = wall::load_wall_atlases(&mut assets)?;
+    let liquid_atlases = liquid::load_liquid_atlases(&mut assets)?;
+    let character = Character::load(&mut assets, &Appearance::new(), &Equipment::new());
+    let mut liquids = LiquidSim::new();
+    let mut lighting = Lighting::new(world);
+    let mut light_overlay = LightOverlay::new(&texture_creator);
+    for frame in 0..frames {
+        if frame > 0 {
+            liquids.step(world);
+        }
+        lighting.finish(world);
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        canvas.clear();
+        view.render(&mut canvas, world, &tile_atlases, &wall_atlases, &liquid_atlases)?;
+        player.render(&character, &mut canvas, view, 1.0)?;
+        light_overlay.render(&mut canvas, view, world, lighting.get_map())?;
+        canvas.present();
+        let path = Path::new(out_dir).join(format!("frame_{:04}.png", frame));
+        save_png(&canvas, &path.to_string_lossy())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use sdl2::image::LoadSurface;
+    use sdl2::rect::Point;
+    use crate::common::Position;
+    use crate::worldgen::WorldGen;
+    use super::*;
+
+    // what seed 42 looks like from where the player starts; after a change that is meant to alter
+    // the picture, look at the new frame and run the test again with UPDATE_GOLDEN=1 to accept it
+    const GOLDEN: &str = "tests/golden/seed_42.png";
+
+    // the size of the PNG at `path` and its pixels as ARGB8888, row after row
+    fn pixels(path: &Path) -> (u32, u32, Vec<u8>) {
+        let surface = Surface::from_file(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        let argb = Surface::new(1, 1, PixelFormatEnum::ARGB8888).unwrap();
+        let surface = surface.convert(&argb.pixel_format()).unwrap();
+        let (w, h, pitch) = (surface.width(), surface.height(), surface.pitch() as usize);
+        let data = surface.without_lock().unwrap();
+        let rows = (0..h as usize).flat_map(|y| data[y * pitch..y * pitch + w as usize * 4].iter().cloned());
+        (w, h, rows.collect())
+    }
+
+    #[test]
+    fn seed_42_renders_like_the_golden_image() {
+        let _image_context = sdl2::image::init(sdl2::image::InitFlag::PNG).unwrap();
+        let mut world = WorldGen::new(42).generate(200, 150).unwrap();
+        let (x, y) = crate::start_point(&world);
+        let mut view = Viewport::new();
+        view.set_pos(Position { x: x as i32, y: y as i32 });
+        let player = Player::new(x as f64 + 0.5, y as f64);
+        let out_dir = env::temp_dir().join(format!("golden_{}", std::process::id()));
+        run(&mut world, &mut view, &player, Size { w: 320, h: 240 }, 1, &out_dir.to_string_lossy()).unwrap();
+        let frame = out_dir.join("frame_0000.png");
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::copy(&frame, GOLDEN).unwrap();
+        }
+        let (actual, expected) = (pixels(&frame), pixels(Path::new(GOLDEN)));
+        assert_eq!((actual.0, actual.1), (expected.0, expected.1), "{} is not the size of {}", frame.display(), GOLDEN);
+        let first_difference = actual.2.chunks(4).zip(expected.2.chunks(4)).position(|(a, b)| a != b)
+            .map(|i| Point::new((i % actual.0 as usize) as i32, (i / actual.0 as usize) as i32));
+        assert!(first_difference.is_none(), "{} differs from {} first at {:?}", frame.display(), GOLDEN,
+                first_difference);
+        let _ = fs::remove_dir_all(&out_dir);
+    }
+}
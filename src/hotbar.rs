@@ -0,0 +1,21 @@
+use sdl2::render::{Canvas, RenderTarget};
+use crate::item::{Inventory, ItemAtlases, ItemStack};
+use crate::ui::{SlotGrid, Ui};
+
+// the first slots of an inventory double as the hotbar
+pub const HOTBAR_SLOTS: usize = 10;
+// the hotbar sits along the top left of the screen; a full inventory continues it downwards
+pub const GRID: SlotGrid = SlotGrid {
+    origin: (10, 10),
+    columns: HOTBAR_SLOTS,
+    slot_size: 44,
+    gap: 4,
+};
+
+// draws the hotbar with the selected slot outlined; returns the slot clicked, if any
+pub fn render_hotbar<T: RenderTarget>(ui: &mut Ui, canvas: &mut Canvas<T>, inventory: &Inventory, selected: usize,
+                                      atlases: &ItemAtlases) -> Result<Option<usize>, String> {
+    let slots: Vec<Option<&ItemStack>> = (0..HOTBAR_SLOTS.min(inventory.len())).map(|i| inventory.get(i)).collect();
+    let clicked = ui.slot_grid(canvas, &GRID, &slots, Some(selected), atlases)?;
+    Ok(clicked.map(|(slot, _)| slot))
+}
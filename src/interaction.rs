@@ -0,0 +1,307 @@
+use sdl2::event::Event;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::render::{Canvas, RenderTarget};
+use crate::assets::{AnimationHandle, Assets};
+use crate::common::Size;
+use crate::dropped_item::DroppedItems;
+use crate::entity::Body;
+use crate::item::ItemStack;
+use crate::item_registry::ITEM_REGISTRY;
+use crate::liquid::Liquid;
+use crate::npc::Npcs;
+use crate::player::Player;
+use crate::tile::{TileId, TileShape};
+use crate::viewport::Viewport;
+use crate::wall::WallId;
+use crate::world::World;
+
+// how far from the edges of the player's hitbox tiles can be reached, in tiles
+const REACH_X: f64 = 5.0;
+const REACH_Y: f64 = 4.0;
+// seconds between placements while the button is held
+const PLACE_INTERVAL: f64 = 0.15;
+// seconds between hits while the button is held
+const SWING_INTERVAL: f64 = 0.4;
+// one frame per stage of mining, from the first crack to the tile about to break
+const CRACK_SHEET: &str = "sprites/TileCracks.png";
+const CURSOR_COLOR: Color = Color { r: 255, g: 255, b: 255, a: 255 };
+
+struct Mining {
+    target: (usize, usize),
+    // hardness worn off so far
+    damage: f64,
+}
+
+// turns the mouse into tile edits with the player's active item: holding the left button hits the
+// NPC under the cursor with a weapon or else mines the tile under it with a tool, and holding the
+// right button places a tile item
+pub struct Interaction<'a> {
+    cracks: AnimationHandle<'a>,
+    // last known cursor position, in pixels
+    cursor: (i32, i32),
+    // held buttons: left, right
+    held: [bool; 2],
+    mining: Option<Mining>,
+    // seconds until the next placement is allowed
+    place_cooldown: f64,
+    // seconds until the next hit is allowed
+    swing_cooldown: f64,
+}
+
+impl<'a> Interaction<'a> {
+    pub fn new<C>(assets: &mut Assets<'a, C>) -> Interaction<'a> {
+        Interaction {
+            cracks: assets.animated_sprite(CRACK_SHEET, None, None),
+            cursor: (0, 0),
+            held: [false; 2],
+            mining: None,
+            place_cooldown: 0.0,
+            swing_cooldown: 0.0,
+        }
+    }
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::MouseMotion { x, y, .. } => self.cursor = (x, y),
+            Event::MouseButtonDown { mouse_btn, x, y, .. } => {
+                self.cursor = (x, y);
+                self.set_held(mouse_btn, true);
+            }
+            Event::MouseButtonUp { mouse_btn, .. } => self.set_held(mouse_btn, false),
+            _ => {}
+        }
+    }
+    fn set_held(&mut self, button: MouseButton, down: bool) {
+        match button {
+            MouseButton::Left => {
+                self.held[0] = down;
+                if !down {
+                    self.mining = None;
+                }
+            }
+            MouseButton::Right => self.held[1] = down,
+            _ => {}
+        }
+    }
+    // the tile under the cursor, if there is one
+    pub fn hovered_tile(&self, view: &Viewport, world: &World) -> Option<(usize, usize)> {
+        let (x, y) = view.screen_to_tile(self.cursor.0, self.cursor.1);
+        if x < 0.0 || y < 0.0 || x >= world.width() as f64 || y >= world.height() as f64 {
+            None
+        } else {
+            Some((x as usize, y as usize))
+        }
+    }
+    // advances hitting, mining and placing by `dt` seconds; placing uses up the player's items,
+    // mined tiles drop into `drops` and hits land on `npcs`
+    pub fn update(&mut self, dt: f64, world: &mut World, view: &Viewport, player: &mut Player, drops: &mut DroppedItems,
+                  npcs: &mut Npcs) {
+        self.place_cooldown = (self.place_cooldown - dt).max(0.0);
+        self.swing_cooldown = (self.swing_cooldown - dt).max(0.0);
+        let target = match self.hovered_tile(view, world) {
+            Some(t) if in_reach(player.get_body(), t.0, t.1) => t,
+            _ => {
+                self.mining = None;
+                return;
+            }
+        };
+        let active = match player.active_item() {
+            Some(stack) => stack.get_id().info(),
+            None => {
+                self.mining = None;
+                return;
+            }
+        };
+        if self.held[0] && active.damage > 0 && self.swing_cooldown == 0.0 {
+            let (x, y) = view.screen_to_tile(self.cursor.0, self.cursor.1);
+            if npcs.hit_at(x, y, active.damage, player.get_body().center().0) {
+                self.swing_cooldown = SWING_INTERVAL;
+                self.mining = None;
+                player.use_item();
+                return;
+            }
+        }
+        if self.held[0] && active.tool_power > 0 {
+            if world.get_tile(target.0, target.1).unwrap().get_id() != TileId::NOTHING {
+                player.use_item();
+            }
+            self.mine(dt, world, target, f64::from(active.tool_power), drops);
+        } else if let (true, Some(tile)) = (self.held[1], active.places) {
+            if self.place_cooldown == 0.0 && can_place(world, player.get_body(), target.0, target.1, tile) {
+                place_tile(world, target.0, target.1, tile);
+                let slot = player.get_selected_slot();
+                player.get_inventory_mut().take(slot, 1);
+                player.use_item();
+                self.place_cooldown = PLACE_INTERVAL;
+            }
+        } else {
+            self.mining = None;
+        }
+    }
+    fn mine(&mut self, dt: f64, world: &mut World, (x, y): (usize, usize), power: f64, drops: &mut DroppedItems) {
+        let id = world.get_tile(x, y).unwrap().get_id();
+        if id == TileId::NOTHING {
+            self.mining = None;
+            return;
+        }
+        let mining = match &mut self.mining {
+            Some(m) if m.target == (x, y) => m,
+            // moving on to another tile starts over
+            m => m.insert(Mining { target: (x, y), damage: 0.0 }),
+        };
+        mining.damage += power * dt;
+        if mining.damage >= f64::from(id.info().hardness) {
+            for (i, j, broken) in break_tile(world, x, y) {
+                if let Some(item) = ITEM_REGISTRY.drop_for(broken) {
+                    drops.spawn(i as f64 + 0.5, j as f64 + 0.5, ItemStack::new(item, 1));
+                }
+            }
+            self.mining = None;
+        }
+    }
+    // draws the cracks on the tile being mined and outlines the tile under the cursor when it is
+    // within reach
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, view: &Viewport, world: &World,
+                                   body: &Body) -> Result<(), String> {
+        if let Some(mining) = &self.mining {
+            let (x, y) = mining.target;
+            let hardness = f64::from(world.get_tile(x, y)?.get_id().info().hardness).max(1.0);
+            let cracks = self.cracks.get();
+            let stages = cracks.frame_count().max(1);
+            let stage = ((mining.damage / hardness * stages as f64) as usize).min(stages - 1);
+            let dest = view.tile_to_screen(x as i32, y as i32);
+            let size = Size { w: dest.width(), h: dest.height() };
+            cracks.render_flipped(canvas, dest.x(), dest.y(), Some(size), (0, stage), false)?;
+        }
+        if let Some((x, y)) = self.hovered_tile(view, world) {
+            if in_reach(body, x, y) {
+                let dest = view.tile_to_screen(x as i32, y as i32);
+                canvas.set_draw_color(CURSOR_COLOR);
+                canvas.draw_rect(dest)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// whether the tile at (x, y) is close enough to `body` to be mined or placed
+pub fn in_reach(body: &Body, x: usize, y: usize) -> bool {
+    let (x, y) = (x as f64, y as f64);
+    x + 1.0 > body.x - REACH_X && x < body.x + body.w + REACH_X
+        && y + 1.0 > body.y - REACH_Y && y < body.bottom() + REACH_Y
+}
+
+// whether `id` can go at (x, y): every cell it covers has to be empty, it has to be built onto a
+// neighboring tile or the wall behind it, solid tiles cannot go where `body` is, and tiles that
+// need support need full solid tiles under the whole of their width
+pub fn can_place(world: &World, body: &Body, x: usize, y: usize, id: TileId) -> bool {
+    if id == TileId::NOTHING {
+        return false;
+    }
+    let cells = match footprint(world, x, y, id) {
+        Some(c) => c,
+        None => return false,
+    };
+    if !cells.iter().all(|&(i, j, _)| world.get_tile(i, j).is_ok_and(|t| t.get_id() == TileId::NOTHING)) {
+        return false;
+    }
+    let info = id.info();
+    if info.solid && cells.iter().any(|&(i, j, _)| {
+        let (fx, fy) = (i as f64, j as f64);
+        body.x < fx + 1.0 && body.x + body.w > fx && body.y < fy + 1.0 && body.bottom() > fy
+    }) {
+        return false;
+    }
+    if info.needs_support && !cells.iter().filter(|c| (c.2).1 == 0).all(|&(i, j, _)| supports(world, i, j + 1)) {
+        return false;
+    }
+    let has_tile = |i: Option<usize>, j: Option<usize>| match (i, j) {
+        (Some(i), Some(j)) => world.get_tile(i, j).is_ok_and(|t| t.get_id() != TileId::NOTHING),
+        _ => false,
+    };
+    has_tile(x.checked_sub(1), Some(y)) || has_tile(Some(x + 1), Some(y))
+        || has_tile(Some(x), y.checked_sub(1)) || has_tile(Some(x), Some(y + 1))
+        || world.get_wall(x, y).is_ok_and(|w| w.get_id() != WallId::NOTHING)
+}
+
+// puts `id` at (x, y), over every cell it covers; a solid tile pushes out whatever liquid was there
+pub fn place_tile(world: &mut World, x: usize, y: usize, id: TileId) {
+    let cells = match footprint(world, x, y, id) {
+        Some(c) => c,
+        None => return,
+    };
+    for (i, j, part) in cells {
+        if world.set_tile(i, j, id).is_err() {
+            continue;
+        }
+        if let Ok(t) = world.get_tile_mut(i, j) {
+            t.set_part(part);
+        }
+        if id.info().solid {
+            let _ = world.set_liquid(i, j, Liquid::new());
+        }
+    }
+}
+
+// clears the tile at (x, y), or the whole of the furniture it is part of, along with anything that
+// was resting on it; returns where each tile or piece of furniture was placed and what it was
+pub fn break_tile(world: &mut World, x: usize, y: usize) -> Vec<(usize, usize, TileId)> {
+    let mut broken = Vec::new();
+    let mut pending = vec![(x, y)];
+    while let Some((x, y)) = pending.pop() {
+        let (ax, ay, id) = match anchor_of(world, x, y) {
+            Some(a) => a,
+            None => continue,
+        };
+        let cells = match footprint(world, ax, ay, id) {
+            Some(c) => c,
+            None => continue,
+        };
+        let top = cells.iter().map(|c| (c.2).1).max().unwrap_or(0);
+        for &(i, j, part) in &cells {
+            if !world.get_tile(i, j).is_ok_and(|t| t.get_id() == id && t.get_part() == part) {
+                continue;
+            }
+            let _ = world.set_tile(i, j, TileId::NOTHING);
+            let above = match j.checked_sub(1) {
+                Some(above) if part.1 == top => above,
+                _ => continue,
+            };
+            if world.get_tile(i, above).is_ok_and(|t| t.get_id() != TileId::NOTHING && t.get_id().info().needs_support) {
+                pending.push((i, above));
+            }
+        }
+        broken.push((ax, ay, id));
+    }
+    broken
+}
+
+// a cell covered by a tile, and which part of the tile is there
+type Cell = (usize, usize, (u8, u8));
+
+// the cells `id` covers when placed at (x, y), bottom row first; `None` if any of them would be
+// outside the world
+fn footprint(world: &World, x: usize, y: usize, id: TileId) -> Option<Vec<Cell>> {
+    let (w, h) = id.info().object.unwrap_or((1, 1));
+    let (w, h) = (w as usize, h as usize);
+    if x + w > world.width() || y >= world.height() || y + 1 < h {
+        return None;
+    }
+    Some((0..h).flat_map(|j| (0..w).map(move |i| (x + i, y - j, (i as u8, j as u8)))).collect())
+}
+
+// the cell the furniture covering (x, y) was placed in and what it is; (x, y) itself for any
+// other tile, and `None` for empty cells
+fn anchor_of(world: &World, x: usize, y: usize) -> Option<(usize, usize, TileId)> {
+    let t = world.get_tile(x, y).ok()?;
+    if t.get_id() == TileId::NOTHING {
+        return None;
+    }
+    let (px, py) = t.get_part();
+    Some((x.checked_sub(usize::from(px))?, y + usize::from(py), t.get_id()))
+}
+
+// whether the tile at (x, y) can hold up a tile that needs support
+fn supports(world: &World, x: usize, y: usize) -> bool {
+    world.get_tile(x, y).is_ok_and(|t| t.get_solid() && t.get_shape() == TileShape::Full)
+}
@@ -0,0 +1,142 @@
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{Canvas, RenderTarget};
+use crate::crafting::RECIPE_BOOK;
+use crate::dropped_item::DroppedItems;
+use crate::hotbar;
+use crate::item::{Inventory, ItemAtlases, ItemStack};
+use crate::player::Player;
+use crate::ui::{self, Ui, TEXT_COLOR};
+use crate::world::World;
+
+// in screen pixels
+const RECIPE_W: u32 = 280;
+const RECIPE_H: u32 = 32;
+const RECIPE_GAP: i32 = 4;
+// craftable recipes listed at once; the wheel scrolls through the rest
+const RECIPE_ROWS: usize = 6;
+// the stack carried by the cursor, drawn below and to the right of it
+const HELD_OFFSET: i32 = 20;
+
+// the whole inventory and what can be crafted from it, opened and closed with Escape; while it is
+// closed only the hotbar shows
+pub struct InventoryScreen {
+    open: bool,
+    // picked up with the mouse and carried by the cursor until it is put down
+    held: Option<ItemStack>,
+    // first craftable recipe listed
+    scroll: usize,
+}
+
+impl InventoryScreen {
+    pub fn new() -> InventoryScreen {
+        InventoryScreen {
+            open: false,
+            held: None,
+            scroll: 0,
+        }
+    }
+    pub fn handle_event(&mut self, event: &Event) {
+        if let Event::KeyDown { keycode: Some(Keycode::Escape), repeat: false, .. } = *event {
+            self.open = !self.open;
+        }
+    }
+    // draws the hotbar, or the inventory and crafting list when open, and does what was clicked
+    pub fn render<T: RenderTarget>(&mut self, ui: &mut Ui, canvas: &mut Canvas<T>, player: &mut Player, world: &World,
+                                   drops: &mut DroppedItems, atlases: &ItemAtlases) -> Result<(), String> {
+        if !self.open {
+            // whatever was carried goes back in, or falls out if there is no room
+            if let Some(left) = self.held.take().and_then(|held| player.get_inventory_mut().add(held)) {
+                let (x, y) = player.get_body().center();
+                drops.spawn(x, y, left);
+            }
+            let selected = player.get_selected_slot();
+            if let Some(slot) = hotbar::render_hotbar(ui, canvas, player.get_inventory(), selected, atlases)? {
+                player.set_selected_slot(slot);
+            }
+            return Ok(());
+        }
+        let grid = hotbar::GRID;
+        let inventory = player.get_inventory();
+        let size = inventory.len();
+        let slots: Vec<Option<&ItemStack>> = (0..size).map(|i| inventory.get(i)).collect();
+        let clicked = ui.slot_grid(canvas, &grid, &slots, Some(player.get_selected_slot()), atlases)?;
+        if let Some((slot, button)) = clicked {
+            self.click_slot(player.get_inventory_mut(), slot, button);
+        }
+        let top = grid.origin.1 + grid.height(size) as i32 + 2 * grid.gap;
+        self.render_crafting(ui, canvas, player, world, (grid.origin.0, top), atlases)?;
+        if let Some(held) = &self.held {
+            let (x, y) = ui.get_mouse();
+            ui.item_icon(canvas, Point::new(x + HELD_OFFSET, y + HELD_OFFSET), grid.slot_size * 3 / 4, held, atlases)?;
+        }
+        Ok(())
+    }
+    // left click picks up, puts down, merges or swaps whole stacks; right click picks up half a
+    // stack, or puts down one of the carried stack
+    fn click_slot(&mut self, inventory: &mut Inventory, slot: usize, button: MouseButton) {
+        match (button, self.held.take()) {
+            (MouseButton::Left, Some(held)) => self.held = inventory.put(slot, held),
+            (MouseButton::Left, None) => self.held = inventory.take(slot, u32::MAX),
+            (MouseButton::Right, None) => self.held = inventory.split(slot),
+            (MouseButton::Right, Some(held)) => {
+                let fits = inventory.get(slot).is_none_or(|s| s.get_id() == held.get_id());
+                self.held = if fits && inventory.put(slot, ItemStack::new(held.get_id(), 1)).is_none() {
+                    (held.get_count() > 1).then(|| ItemStack::new(held.get_id(), held.get_count() - 1))
+                } else {
+                    Some(held)
+                };
+            }
+            (_, held) => self.held = held,
+        }
+    }
+    // lists what can be made from the inventory at the stations near the player; clicking a recipe
+    // makes it once
+    fn render_crafting<T: RenderTarget>(&mut self, ui: &mut Ui, canvas: &mut Canvas<T>, player: &mut Player,
+                                        world: &World, (left, top): (i32, i32), atlases: &ItemAtlases) -> Result<(), String> {
+        let stations = RECIPE_BOOK.nearby_stations(world, player.get_body());
+        let craftable = RECIPE_BOOK.craftable(player.get_inventory(), &stations);
+        let title_h = ui.measure("Crafting").h as i32;
+        let shown = craftable.len().clamp(1, RECIPE_ROWS) as i32;
+        let height = title_h + RECIPE_GAP + shown * (RECIPE_H as i32 + RECIPE_GAP) + RECIPE_GAP;
+        let panel = Rect::new(left, top, RECIPE_W + 2 * RECIPE_GAP as u32, height as u32);
+        ui.panel(canvas, panel)?;
+        ui.label(canvas, left + RECIPE_GAP, top + RECIPE_GAP, "Crafting", TEXT_COLOR)?;
+        let max_scroll = craftable.len().saturating_sub(RECIPE_ROWS);
+        self.scroll = (self.scroll as i64 - i64::from(ui.scrolled(panel))).clamp(0, max_scroll as i64) as usize;
+        if craftable.is_empty() {
+            let y = top + title_h + 2 * RECIPE_GAP + (RECIPE_H - ui.measure("-").h) as i32 / 2;
+            return ui.label(canvas, left + 2 * RECIPE_GAP, y, "Nothing", TEXT_COLOR);
+        }
+        for (row, &index) in craftable.iter().skip(self.scroll).take(RECIPE_ROWS).enumerate() {
+            let recipe = match RECIPE_BOOK.get(index) {
+                Some(r) => r,
+                None => continue,
+            };
+            let y = top + title_h + 2 * RECIPE_GAP + row as i32 * (RECIPE_H as i32 + RECIPE_GAP);
+            let rect = Rect::new(left + RECIPE_GAP, y, RECIPE_W, RECIPE_H);
+            let name = ui::display_name(&recipe.result.get_id().info().name);
+            if ui.button(canvas, rect, &name)? {
+                // the list only holds recipes that can be made, so this fails only when the
+                // result has nowhere to go
+                if let Err(msg) = RECIPE_BOOK.craft(index, player.get_inventory_mut(), &stations) {
+                    println!("{}", msg);
+                }
+            }
+            let icon = Point::new(rect.x() + RECIPE_H as i32 / 2, rect.center().y());
+            ui.item_icon(canvas, icon, RECIPE_H - 2 * RECIPE_GAP as u32, &recipe.result, atlases)?;
+            if ui.hovered(rect) {
+                let mut needs: Vec<String> = recipe.ingredients.iter()
+                    .map(|i| format!("{} {}", i.get_count(), ui::display_name(&i.get_id().info().name)))
+                    .collect();
+                if let Some(station) = recipe.station {
+                    needs.push(format!("at a {}", ui::display_name(&station.info().name)));
+                }
+                ui.tooltip(&needs.join("\n"));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use crate::assets::{Assets, SpriteHandle};
+use crate::item_registry::{ItemInfo, ITEM_REGISTRY};
+
+// numeric item id; what each id means comes from the item registry
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+#[derive(Hash)]
+pub struct ItemId(pub u16);
+
+impl ItemId {
+    // no item; never registered
+    pub const NOTHING: ItemId = ItemId(0);
+    pub fn info(self) -> &'static ItemInfo {
+        ITEM_REGISTRY.get(self)
+    }
+}
+
+impl From<ItemId> for u16 {
+    fn from(id: ItemId) -> u16 {
+        id.0
+    }
+}
+
+// some number of one kind of item; never empty and never over the item's max stack
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct ItemStack {
+    id: ItemId,
+    count: u32,
+}
+
+impl ItemStack {
+    pub fn new(id: ItemId, count: u32) -> ItemStack {
+        ItemStack { id, count }
+    }
+    pub fn get_id(&self) -> ItemId {
+        self.id
+    }
+    pub fn get_count(&self) -> u32 {
+        self.count
+    }
+    // moves as much of `other` onto this stack as fits; returns what is left of it
+    fn merge(&mut self, other: ItemStack) -> Option<ItemStack> {
+        if other.id != self.id {
+            return Some(other);
+        }
+        let moved = other.count.min(self.id.info().max_stack.saturating_sub(self.count));
+        self.count += moved;
+        if moved == other.count {
+            None
+        } else {
+            Some(ItemStack::new(other.id, other.count - moved))
+        }
+    }
+}
+
+// a fixed number of slots that each hold one stack or nothing
+#[derive(Clone)]
+pub struct Inventory {
+    slots: Vec<Option<ItemStack>>,
+}
+
+impl Inventory {
+    pub fn new(size: usize) -> Inventory {
+        Inventory { slots: vec![None; size] }
+    }
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+    pub fn get(&self, slot: usize) -> Option<&ItemStack> {
+        self.slots.get(slot).and_then(|s| s.as_ref())
+    }
+    // how many of `id` there are across every slot
+    pub fn count(&self, id: ItemId) -> u32 {
+        self.slots.iter().flatten().filter(|s| s.id == id).map(|s| s.count).sum()
+    }
+    pub fn counts(&self) -> HashMap<ItemId, u32> {
+        let mut counts = HashMap::new();
+        for stack in self.slots.iter().flatten() {
+            *counts.entry(stack.id).or_insert(0) += stack.count;
+        }
+        counts
+    }
+    // takes `count` of `id` from wherever they are, last slots first so that the hotbar is
+    // emptied last; takes nothing and returns false if there are not enough
+    pub fn remove(&mut self, id: ItemId, count: u32) -> bool {
+        if self.count(id) < count {
+            return false;
+        }
+        let mut left = count;
+        for slot in self.slots.iter_mut().rev() {
+            if left == 0 {
+                break;
+            }
+            if let Some(s) = slot {
+                if s.id == id {
+                    let taken = left.min(s.count);
+                    s.count -= taken;
+                    left -= taken;
+                    if s.count == 0 {
+                        *slot = None;
+                    }
+                }
+            }
+        }
+        true
+    }
+    // whether at least one of `id` would fit
+    pub fn can_accept(&self, id: ItemId) -> bool {
+        let max_stack = id.info().max_stack;
+        self.slots.iter().any(|s| match s {
+            Some(stack) => stack.id == id && stack.count < max_stack,
+            None => true,
+        })
+    }
+    // tops up stacks of the same item first, then fills empty slots in order; returns whatever
+    // did not fit
+    pub fn add(&mut self, stack: ItemStack) -> Option<ItemStack> {
+        let mut left = Some(stack);
+        for slot in self.slots.iter_mut().flatten() {
+            left = match left {
+                Some(l) => slot.merge(l),
+                None => return None,
+            };
+        }
+        for slot in self.slots.iter_mut().filter(|s| s.is_none()) {
+            let l = left?;
+            let max_stack = l.id.info().max_stack;
+            *slot = Some(ItemStack::new(l.id, l.count.min(max_stack)));
+            left = if l.count > max_stack { Some(ItemStack::new(l.id, l.count - max_stack)) } else { None };
+        }
+        left
+    }
+    // removes up to `count` items from a slot
+    pub fn take(&mut self, slot: usize, count: u32) -> Option<ItemStack> {
+        let stack = self.slots.get_mut(slot)?;
+        let s = stack.as_mut()?;
+        if count == 0 {
+            return None;
+        }
+        if count >= s.count {
+            stack.take()
+        } else {
+            s.count -= count;
+            Some(ItemStack::new(s.id, count))
+        }
+    }
+    // takes the larger half of a slot's stack, leaving the rest
+    pub fn split(&mut self, slot: usize) -> Option<ItemStack> {
+        let count = self.get(slot)?.count;
+        self.take(slot, count.div_ceil(2))
+    }
+    // puts a stack into a slot the way a held stack is dropped onto it: the same item merges in
+    // and anything else trades places; returns what ends up held
+    pub fn put(&mut self, slot: usize, stack: ItemStack) -> Option<ItemStack> {
+        match self.slots.get_mut(slot) {
+            Some(Some(s)) if s.id == stack.id => s.merge(stack),
+            Some(s) => s.replace(stack),
+            None => Some(stack),
+        }
+    }
+}
+
+// one sprite per registered item, keyed by id
+pub type ItemAtlases<'a> = HashMap<ItemId, SpriteHandle<'a>>;
+
+pub fn load_item_atlases<'tc, C>(assets: &mut Assets<'tc, C>) -> Result<ItemAtlases<'tc>, String> {
+    let mut item_atlases = ItemAtlases::new();
+    for info in ITEM_REGISTRY.iter() {
+        item_atlases.insert(info.id, assets.sprite(&info.sprite, None));
+    }
+    Ok(item_atlases)
+}
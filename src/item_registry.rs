@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+use serde::Deserialize;
+use crate::item::ItemId;
+use crate::registry::{self, Entry, Registry};
+use crate::tile::TileId;
+use crate::tile_registry::TILE_REGISTRY;
+
+pub const ITEM_REGISTRY_FILE: &str = "data/items.toml";
+
+lazy_static! {
+    // every item the game knows about, read once from `ITEM_REGISTRY_FILE`
+    pub static ref ITEM_REGISTRY: ItemRegistry = ItemRegistry::load_from_file(ITEM_REGISTRY_FILE)
+        .unwrap_or_else(|msg| panic!("ERROR: Could not load the item registry: {}", msg));
+}
+
+// everything about a kind of item that is not stored per stack
+#[derive(Debug)]
+pub struct ItemInfo {
+    pub id: ItemId,
+    pub name: String,
+    pub sprite: String,
+    pub max_stack: u32,
+    // tile placed when the item is used on an empty cell
+    pub places: Option<TileId>,
+    // hardness mined per second while held; 0 for items that are not tools
+    pub tool_power: u32,
+    // dealt to NPCs hit with it; 0 for items that are not weapons
+    pub damage: u32,
+}
+
+// an `[[item]]` table as written in the registry file
+#[derive(Deserialize)]
+struct ItemEntry {
+    id: u16,
+    name: String,
+    sprite: String,
+    #[serde(default = "default_max_stack")]
+    max_stack: u32,
+    places: Option<String>,
+    #[serde(default)]
+    drops_from: Vec<String>,
+    #[serde(default)]
+    tool_power: u32,
+    #[serde(default)]
+    damage: u32,
+}
+
+fn default_max_stack() -> u32 {
+    999
+}
+
+#[derive(Deserialize)]
+struct RegistryFile {
+    #[serde(default)]
+    item: Vec<ItemEntry>,
+}
+
+pub struct ItemRegistry {
+    items: Registry<ItemInfo>,
+    // what mining each tile gives
+    drops: HashMap<TileId, ItemId>,
+}
+
+impl Entry for ItemInfo {
+    type Id = ItemId;
+    const KIND: &'static str = "Item";
+    fn id(&self) -> ItemId {
+        self.id
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+// lookups by id and name go straight to the items
+impl Deref for ItemRegistry {
+    type Target = Registry<ItemInfo>;
+    fn deref(&self) -> &Registry<ItemInfo> {
+        &self.items
+    }
+}
+
+impl ItemRegistry {
+    pub fn load_from_file(path: &str) -> Result<ItemRegistry, String> {
+        registry::load_file(path, ItemRegistry::parse)
+    }
+    pub fn parse(text: &str) -> Result<ItemRegistry, String> {
+        let file: RegistryFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        let mut registry = ItemRegistry {
+            items: Registry::default(),
+            drops: HashMap::new(),
+        };
+        let tile = |item: &str, name: &str| TILE_REGISTRY.id(name)
+            .ok_or_else(|| format!("Item {} refers to unknown tile {}", item, name));
+        for entry in &file.item {
+            if entry.id == ItemId::NOTHING.0 {
+                return Err(format!("Item {} uses the reserved id 0", entry.name));
+            }
+            if entry.max_stack == 0 {
+                return Err(format!("Item {} has a max_stack of 0", entry.name));
+            }
+            let places = match &entry.places {
+                Some(name) => Some(tile(&entry.name, name)?),
+                None => None,
+            };
+            let mut dropped_by: Vec<TileId> = places.into_iter().collect();
+            for name in &entry.drops_from {
+                dropped_by.push(tile(&entry.name, name)?);
+            }
+            for t in dropped_by {
+                if let Some(other) = registry.drops.insert(t, ItemId(entry.id)) {
+                    if other != ItemId(entry.id) {
+                        return Err(format!("Tile {} drops both item {} and item {}", t.info().name, other.0, entry.id));
+                    }
+                }
+            }
+            registry.items.insert(ItemInfo {
+                id: ItemId(entry.id),
+                name: entry.name.clone(),
+                sprite: entry.sprite.clone(),
+                max_stack: entry.max_stack,
+                places,
+                tool_power: entry.tool_power,
+                damage: entry.damage,
+            })?;
+        }
+        Ok(registry)
+    }
+    // the item mining `tile` gives, if any
+    pub fn drop_for(&self, tile: TileId) -> Option<ItemId> {
+        self.drops.get(&tile).cloned()
+    }
+}
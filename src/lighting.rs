@@ -0,0 +1,358 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use sdl2::hint;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, RenderTarget, Texture, TextureCreator};
+use crate::viewport::Viewport;
+use crate::wall::WallId;
+use crate::world::World;
+
+// RGB light level, same layout as `TileInfo::light`
+pub type Light = (u8, u8, u8);
+
+pub const DARK: Light = (0, 0, 0);
+pub const SKY_LIGHT: Light = (255, 255, 255);
+// how much light is lost stepping into an open or a solid cell
+const AIR_FALLOFF: u8 = 16;
+const SOLID_FALLOFF: u8 = 64;
+// no light travels further than this many cells from where it started, so an edit can only
+// change the light this close to it
+const REACH: usize = 255 / AIR_FALLOFF as usize + 1;
+
+// what the lighting needs to know about a cell
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+struct LightCell {
+    solid: bool,
+    // solid tiles and walls both keep the sky out of everything below them
+    blocks_sky: bool,
+    emit: Light,
+}
+
+impl LightCell {
+    fn of(world: &World, x: usize, y: usize) -> LightCell {
+        let tile = world.get_tile(x, y).unwrap();
+        let wall = world.get_wall(x, y).unwrap();
+        LightCell {
+            solid: tile.get_solid(),
+            blocks_sky: tile.get_solid() || wall.get_id() != WallId::NOTHING,
+            emit: tile.get_id().info().light,
+        }
+    }
+}
+
+enum Job {
+    Edit(Vec<(usize, usize, LightCell)>),
+    Stop,
+}
+
+// freshly computed light for the cells [x, x + w) x [y, y + h), row-major
+struct Patch {
+    x: usize,
+    y: usize,
+    w: usize,
+    light: Vec<Light>,
+}
+
+// the light level of every cell, as of the last patch from the worker; until the first full
+// pass comes back everything is lit
+pub struct LightMap {
+    width: usize,
+    height: usize,
+    light: Vec<Light>,
+}
+
+impl LightMap {
+    fn new(width: usize, height: usize) -> LightMap {
+        LightMap {
+            width,
+            height,
+            light: vec![SKY_LIGHT; width * height],
+        }
+    }
+    // out of bounds cells are dark
+    pub fn get(&self, x: usize, y: usize) -> Light {
+        if x >= self.width || y >= self.height {
+            DARK
+        } else {
+            self.light[y * self.width + x]
+        }
+    }
+    fn apply(&mut self, patch: &Patch) {
+        for (j, row) in patch.light.chunks(patch.w).enumerate() {
+            let start = (patch.y + j) * self.width + patch.x;
+            self.light[start..start + patch.w].copy_from_slice(row);
+        }
+    }
+}
+
+// keeps a `LightMap` up to date with a world; all of the propagation happens on a worker thread
+// that owns its own copy of the cells, so the render loop only ever sends edits and picks up
+// finished patches
+pub struct Lighting {
+    map: LightMap,
+    jobs: Sender<Job>,
+    // the worker answers every job, including the first full pass, with one batch of patches
+    patches: Receiver<Vec<Patch>>,
+    pending: usize,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Lighting {
+    // starts the worker on a full pass over `world`
+    pub fn new(world: &World) -> Lighting {
+        let (width, height) = (world.width(), world.height());
+        let mut cells = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                cells.push(LightCell::of(world, x, y));
+            }
+        }
+        let (jobs, job_queue) = mpsc::channel();
+        let (patch_queue, patches) = mpsc::channel();
+        let worker = thread::spawn(move || {
+            let mut solver = Solver::new(width, height, cells);
+            if patch_queue.send(vec![solver.relight(0, 0, width, height)]).is_err() {
+                return;
+            }
+            while let Ok(Job::Edit(edits)) = job_queue.recv() {
+                if patch_queue.send(solver.edit(edits)).is_err() {
+                    return;
+                }
+            }
+        });
+        Lighting {
+            map: LightMap::new(width, height),
+            jobs,
+            patches,
+            pending: 1,
+            worker: Some(worker),
+        }
+    }
+    pub fn get_map(&self) -> &LightMap {
+        &self.map
+    }
+    // sends the world's edits since the last call to the worker and applies whatever it has
+    // finished; never waits on the worker
+    pub fn update(&mut self, world: &mut World) {
+        self.send_edits(world);
+        while let Ok(batch) = self.patches.try_recv() {
+            self.apply(batch);
+        }
+    }
+    // like `update`, but waits until the map has caught up with every edit so far; for when the
+    // frame has to be exact rather than on time, such as captures
+    pub fn finish(&mut self, world: &mut World) {
+        self.send_edits(world);
+        while self.pending > 0 {
+            match self.patches.recv() {
+                Ok(batch) => self.apply(batch),
+                Err(_) => break,
+            }
+        }
+    }
+    fn send_edits(&mut self, world: &mut World) {
+        let edits: Vec<_> = world.take_light_changes().into_iter()
+            .map(|(x, y)| (x, y, LightCell::of(world, x, y)))
+            .collect();
+        // a worker that has gone away has nothing left to answer
+        if !edits.is_empty() && self.jobs.send(Job::Edit(edits)).is_ok() {
+            self.pending += 1;
+        }
+    }
+    fn apply(&mut self, batch: Vec<Patch>) {
+        for patch in &batch {
+            self.map.apply(patch);
+        }
+        self.pending -= 1;
+    }
+}
+
+impl Drop for Lighting {
+    fn drop(&mut self) {
+        let _ = self.jobs.send(Job::Stop);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+// the worker's side: its own copy of the cells and of the light
+struct Solver {
+    width: usize,
+    height: usize,
+    cells: Vec<LightCell>,
+    light: Vec<Light>,
+    // y of the first cell in each column that blocks the sky; everything above it is sky
+    sky_depth: Vec<usize>,
+}
+
+impl Solver {
+    fn new(width: usize, height: usize, cells: Vec<LightCell>) -> Solver {
+        let mut solver = Solver {
+            width,
+            height,
+            cells,
+            light: vec![DARK; width * height],
+            sky_depth: vec![height; width],
+        };
+        for x in 0..width {
+            solver.sky_depth[x] = solver.find_sky_depth(x);
+        }
+        solver
+    }
+    fn find_sky_depth(&self, x: usize) -> usize {
+        (0..self.height).find(|&y| self.cells[y * self.width + x].blocks_sky).unwrap_or(self.height)
+    }
+    // applies a batch of edits and relights the area each one can have touched
+    fn edit(&mut self, edits: Vec<(usize, usize, LightCell)>) -> Vec<Patch> {
+        let mut regions = Vec::new();
+        for (x, y, cell) in edits {
+            let index = y * self.width + x;
+            if self.cells[index] == cell {
+                continue;
+            }
+            self.cells[index] = cell;
+            let old_depth = self.sky_depth[x];
+            let new_depth = self.find_sky_depth(x);
+            self.sky_depth[x] = new_depth;
+            // a change in sky exposure relights the whole stretch of the column it uncovered
+            let (mut top, mut bottom) = (y, y);
+            if old_depth != new_depth {
+                top = top.min(old_depth.min(new_depth));
+                bottom = bottom.max(old_depth.max(new_depth).min(self.height - 1));
+            }
+            let top = top.saturating_sub(REACH);
+            let bottom = (bottom + REACH + 1).min(self.height);
+            let left = x.saturating_sub(REACH);
+            let right = (x + REACH + 1).min(self.width);
+            regions.push((left, top, right, bottom));
+        }
+        // every cell is written before any region is relit, and regions are wide enough that the
+        // light on their border cannot depend on what changed inside them
+        regions.into_iter().map(|(x0, y0, x1, y1)| self.relight(x0, y0, x1, y1)).collect()
+    }
+    // recomputes the light of the cells [x0, x1) x [y0, y1), taking the light on the ring just
+    // outside as fixed, and returns the result
+    fn relight(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) -> Patch {
+        let mut queue = VecDeque::new();
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let index = y * self.width + x;
+                let sky = if y < self.sky_depth[x] { SKY_LIGHT } else { DARK };
+                let seed = brightest(sky, self.cells[index].emit);
+                self.light[index] = seed;
+                if seed != DARK {
+                    queue.push_back((x, y));
+                }
+            }
+        }
+        // light coming in from outside the region
+        let ring_x0 = x0.saturating_sub(1);
+        let ring_y0 = y0.saturating_sub(1);
+        let ring_x1 = (x1 + 1).min(self.width);
+        let ring_y1 = (y1 + 1).min(self.height);
+        for y in ring_y0..ring_y1 {
+            for x in ring_x0..ring_x1 {
+                let inside = x >= x0 && x < x1 && y >= y0 && y < y1;
+                if !inside && self.light[y * self.width + x] != DARK {
+                    queue.push_back((x, y));
+                }
+            }
+        }
+        while let Some((x, y)) = queue.pop_front() {
+            let here = self.light[y * self.width + x];
+            let neighbors = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for &(nx, ny) in neighbors.iter() {
+                if nx < x0 || nx >= x1 || ny < y0 || ny >= y1 {
+                    continue;
+                }
+                let index = ny * self.width + nx;
+                let falloff = if self.cells[index].solid { SOLID_FALLOFF } else { AIR_FALLOFF };
+                let reached = dim(here, falloff);
+                let current = self.light[index];
+                let lit = brightest(current, reached);
+                if lit != current {
+                    self.light[index] = lit;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+        let mut light = Vec::with_capacity((x1 - x0) * (y1 - y0));
+        for y in y0..y1 {
+            light.extend_from_slice(&self.light[y * self.width + x0..y * self.width + x1]);
+        }
+        Patch { x: x0, y: y0, w: x1 - x0, light }
+    }
+}
+
+fn brightest(a: Light, b: Light) -> Light {
+    (a.0.max(b.0), a.1.max(b.1), a.2.max(b.2))
+}
+
+fn dim(l: Light, by: u8) -> Light {
+    (l.0.saturating_sub(by), l.1.saturating_sub(by), l.2.saturating_sub(by))
+}
+
+// multiplies the scene by the light map; the map is uploaded at one texel per tile and stretched
+// with linear filtering, which smooths the light out between tile centers
+pub struct LightOverlay<'tc, C> {
+    texture_creator: &'tc TextureCreator<C>,
+    texture: Option<Texture<'tc>>,
+    texture_size: (u32, u32),
+}
+
+impl<'tc, C> LightOverlay<'tc, C> {
+    pub fn new(texture_creator: &'tc TextureCreator<C>) -> LightOverlay<'tc, C> {
+        LightOverlay {
+            texture_creator,
+            texture: None,
+            texture_size: (0, 0),
+        }
+    }
+    pub fn render<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, view: &Viewport, world: &World, map: &LightMap)
+                  -> Result<(), String> {
+        let bounds = view.visible_tiles(world);
+        if bounds.width() == 0 || bounds.height() == 0 {
+            return Ok(());
+        }
+        let (w, h) = (bounds.width(), bounds.height());
+        if self.texture.is_none() || self.texture_size.0 < w || self.texture_size.1 < h {
+            // the filtering hint is read when a texture is created, so it only affects this one
+            hint::set("SDL_RENDER_SCALE_QUALITY", "1");
+            let created = self.texture_creator.create_texture_streaming(PixelFormatEnum::RGB24, w, h);
+            hint::set("SDL_RENDER_SCALE_QUALITY", "0");
+            let mut texture = created.map_err(|e| format!("Could not create the light map texture: {}", e))?;
+            texture.set_blend_mode(BlendMode::Mod);
+            self.texture = Some(texture);
+            self.texture_size = (w, h);
+        }
+        let texture = self.texture.as_mut().unwrap();
+        let area = Rect::new(0, 0, w, h);
+        texture.with_lock(area, |pixels, pitch| {
+            for j in 0..h as usize {
+                for i in 0..w as usize {
+                    let l = map.get(bounds.x() as usize + i, bounds.y() as usize + j);
+                    let p = j * pitch + i * 3;
+                    pixels[p] = l.0;
+                    pixels[p + 1] = l.1;
+                    pixels[p + 2] = l.2;
+                }
+            }
+        })?;
+        let top_left = view.tile_to_screen(bounds.x(), bounds.y());
+        let bottom_right = view.tile_to_screen(bounds.right() - 1, bounds.bottom() - 1);
+        let dest = Rect::new(top_left.x(), top_left.y(), (bottom_right.right() - top_left.x()) as u32,
+                             (bottom_right.bottom() - top_left.y()) as u32);
+        canvas.copy(texture, area, dest)
+    }
+}
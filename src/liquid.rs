@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use sdl2::render::{BlendMode, Canvas, RenderTarget};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use crate::assets::{Assets, SpriteHandle};
+use crate::common::Size;
+use crate::world::World;
+
+pub const MAX_LIQUID: u8 = 255;
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+#[derive(Hash)]
+pub enum LiquidKind {
+    Water,
+    Lava,
+    Honey,
+}
+
+impl LiquidKind {
+    pub const ALL: [LiquidKind; 3] = [LiquidKind::Water, LiquidKind::Lava, LiquidKind::Honey];
+    // inverse of `kind as u8`, used when reading liquids back from disk
+    pub fn from_u8(n: u8) -> Option<LiquidKind> {
+        LiquidKind::ALL.get(usize::from(n)).cloned()
+    }
+    pub fn texture(self) -> &'static str {
+        match self {
+            LiquidKind::Water => "sprites/Liquid_0.png",
+            LiquidKind::Lava => "sprites/Liquid_1.png",
+            LiquidKind::Honey => "sprites/Liquid_11.png",
+        }
+    }
+    fn alpha(self) -> u8 {
+        match self {
+            LiquidKind::Water => 160,
+            LiquidKind::Lava => 230,
+            LiquidKind::Honey => 200,
+        }
+    }
+    // thicker liquids only move every few ticks
+    fn flow_interval(self) -> u64 {
+        match self {
+            LiquidKind::Water => 1,
+            LiquidKind::Lava => 3,
+            LiquidKind::Honey => 4,
+        }
+    }
+}
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+pub struct Liquid {
+    // how full the cell is, 0 (dry) to `MAX_LIQUID`
+    amount: u8,
+    kind: LiquidKind,
+}
+
+impl Liquid {
+    pub const fn new() -> Liquid {
+        Liquid {
+            amount: 0,
+            kind: LiquidKind::Water,
+        }
+    }
+    pub fn of(kind: LiquidKind, amount: u8) -> Liquid {
+        Liquid { amount, kind }
+    }
+    pub fn get_amount(&self) -> u8 {
+        self.amount
+    }
+    pub fn get_kind(&self) -> LiquidKind {
+        self.kind
+    }
+    pub fn is_empty(&self) -> bool {
+        self.amount == 0
+    }
+    // whether liquid of `kind` may flow into this cell
+    fn accepts(&self, kind: LiquidKind) -> bool {
+        self.amount == 0 || self.kind == kind
+    }
+    // `dest` is the cell's square on screen; partially filled cells are drawn from the bottom up
+    // unless `full_height` is set because there is more liquid right above
+    pub fn render<T: RenderTarget>(&self, atlases: &LiquidAtlases, canvas: &mut Canvas<T>, dest: Rect, full_height: bool) -> Result<(), String> {
+        let sprite = match atlases.get(&self.kind) {
+            Some(s) => s.get(),
+            None => return Ok(()),
+        };
+        let fill = if full_height { 1.0 } else { f64::from(self.amount) / f64::from(MAX_LIQUID) };
+        let h = ((dest.height() as f64 * fill).round() as u32).max(1);
+        let clip_h = ((sprite.height().min(16) as f64 * fill).round() as u32).max(1);
+        sprite.render(canvas, dest.x(), dest.bottom() - h as i32, Some(Size { w: dest.width(), h }),
+                      Some(Rect::new(0, 0, 16, clip_h)))
+    }
+}
+
+// advances liquids in the chunks the world has marked as active; cells are visited bottom row
+// first so that liquid falls one cell per tick instead of dropping down a whole column at once,
+// and sideways sweeps alternate direction every tick so neither side is favored
+pub struct LiquidSim {
+    tick: u64,
+}
+
+impl LiquidSim {
+    pub fn new() -> LiquidSim {
+        LiquidSim { tick: 0 }
+    }
+    pub fn step(&mut self, world: &mut World) {
+        let left_to_right = self.tick.is_multiple_of(2);
+        for (cx, cy) in world.take_active_liquid_chunks() {
+            let (x0, y0, x1, y1) = world.chunk_cell_span(cx, cy);
+            for y in (y0..y1).rev() {
+                for i in 0..(x1 - x0) {
+                    let x = if left_to_right { x0 + i } else { x1 - 1 - i };
+                    self.update_cell(world, x, y);
+                }
+            }
+        }
+        self.tick += 1;
+    }
+    fn update_cell(&self, world: &mut World, x: usize, y: usize) {
+        let here = *world.get_liquid(x, y).unwrap();
+        if here.is_empty() {
+            return;
+        }
+        if !self.tick.is_multiple_of(here.kind.flow_interval()) {
+            // keep the chunk awake until the tick this liquid gets to move on
+            world.wake_liquid(x, y);
+            return;
+        }
+        let kind = here.kind;
+        let mut amount = here.amount;
+        // fall
+        if let Some(below) = open_cell(world, x, y + 1, kind) {
+            let moved = amount.min(MAX_LIQUID - below.amount);
+            if moved > 0 {
+                amount -= moved;
+                world.set_liquid(x, y + 1, Liquid::of(kind, below.amount + moved)).unwrap();
+            }
+        }
+        // level out with whichever sides are open
+        let mut sides = Vec::with_capacity(2);
+        if x > 0 {
+            if let Some(l) = open_cell(world, x - 1, y, kind) {
+                sides.push((x - 1, l.amount));
+            }
+        }
+        if let Some(r) = open_cell(world, x + 1, y, kind) {
+            sides.push((x + 1, r.amount));
+        }
+        if amount > 0 && sides.iter().any(|&(_, a)| a.saturating_add(1) < amount) {
+            let total = u32::from(amount) + sides.iter().map(|&(_, a)| u32::from(a)).sum::<u32>();
+            let cells = sides.len() as u32 + 1;
+            let (share, remainder) = (total / cells, total % cells);
+            // the remainder is handed out one unit at a time, this cell first; no cell goes over
+            // the maximum because a full share only happens when every cell is full
+            amount = (share + u32::from(remainder > 0)) as u8;
+            for (i, &(sx, a)) in sides.iter().enumerate() {
+                let level = (share + u32::from(i as u32 + 1 < remainder)) as u8;
+                if a != level {
+                    world.set_liquid(sx, y, Liquid::of(kind, level)).unwrap();
+                }
+            }
+        }
+        if amount != here.amount {
+            world.set_liquid(x, y, Liquid::of(kind, amount)).unwrap();
+        }
+    }
+}
+
+// the liquid at (x, y) if liquid of `kind` could flow into it
+fn open_cell(world: &World, x: usize, y: usize, kind: LiquidKind) -> Option<Liquid> {
+    let solid = world.get_tile(x, y).ok()?.get_solid();
+    let liquid = *world.get_liquid(x, y).ok()?;
+    if solid || !liquid.accepts(kind) {
+        None
+    } else {
+        Some(liquid)
+    }
+}
+
+// one sheet per kind of liquid
+pub type LiquidAtlases<'a> = HashMap<LiquidKind, SpriteHandle<'a>>;
+
+pub fn load_liquid_atlases<'tc, C>(assets: &mut Assets<'tc, C>) -> Result<LiquidAtlases<'tc>, String> {
+    let magenta = Color { r: 255, g: 0, b: 255, a: 255 };
+    let mut liquid_atlases = LiquidAtlases::new();
+    for kind in LiquidKind::ALL.iter() {
+        let sprite = assets.sprite(kind.texture(), Some(magenta));
+        sprite.get_mut().set_blend_mode(BlendMode::Blend);
+        sprite.get_mut().set_alpha(kind.alpha());
+        liquid_atlases.insert(*kind, sprite);
+    }
+    Ok(liquid_atlases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lava_placed_between_flow_ticks_still_spreads() {
+        let mut world = World::new(64, 64);
+        let mut sim = LiquidSim::new();
+        sim.step(&mut world);
+        assert!(!sim.tick.is_multiple_of(LiquidKind::Lava.flow_interval()));
+        // the bottom edge of the world is a floor
+        world.set_liquid(10, 63, Liquid::of(LiquidKind::Lava, MAX_LIQUID)).unwrap();
+        for _ in 0..12 {
+            sim.step(&mut world);
+        }
+        assert!(!world.get_liquid(9, 63).unwrap().is_empty());
+        assert!(!world.get_liquid(11, 63).unwrap().is_empty());
+    }
+
+    #[test]
+    fn flowing_liquid_keeps_its_volume() {
+        let mut world = World::new(64, 64);
+        let mut sim = LiquidSim::new();
+        // a column of full cells with one nearly full cell on top, so the shares rarely divide evenly
+        for y in 50..64 {
+            world.set_liquid(20, y, Liquid::of(LiquidKind::Water, MAX_LIQUID)).unwrap();
+        }
+        world.set_liquid(20, 49, Liquid::of(LiquidKind::Water, MAX_LIQUID - 1)).unwrap();
+        let volume = |world: &World| -> u32 {
+            (0..64).flat_map(|y| (0..64).map(move |x| (x, y)))
+                .map(|(x, y)| u32::from(world.get_liquid(x, y).unwrap().get_amount()))
+                .sum()
+        };
+        let before = volume(&world);
+        for _ in 0..200 {
+            sim.step(&mut world);
+            assert_eq!(volume(&world), before);
+        }
+    }
+}
@@ -1,122 +1,380 @@
 #[macro_use]
 extern crate lazy_static;
-#[macro_use]
-extern crate strum_macros;
-extern crate enum_map;
 extern crate sdl2;
-extern crate strum;
+extern crate serde;
+extern crate toml;
 
 mod animated_sprite;
+mod animation;
+mod assets;
+mod atlas;
+mod autotile;
+mod camera;
+mod character;
+mod chunk;
 mod common;
+mod crafting;
+mod dropped_item;
+mod entity;
+mod font;
+mod game_loop;
+mod headless;
+mod hotbar;
+mod interaction;
+mod inventory_screen;
+mod item;
+mod item_registry;
+mod lighting;
+mod liquid;
+mod npc;
+mod npc_ai;
+mod npc_registry;
+mod player;
+mod registry;
+mod save;
 mod sprite;
+mod sprite_sheet;
 mod tile;
+mod tile_registry;
+mod ui;
 mod viewport;
+mod wall;
 mod world;
+mod worldgen;
 
-use animated_sprite::AnimatedSprite;
-use enum_map::EnumMap;
-use sprite::Sprite;
+use std::time::{Duration, Instant};
 use sdl2::Sdl;
+use sdl2::EventPump;
 use sdl2::VideoSubsystem;
 use sdl2::event::Event;
 use sdl2::event::WindowEvent;
 use sdl2::image::InitFlag;
 use sdl2::pixels::Color;
-use sdl2::video::Window;
 use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+use sdl2::video::{Window, WindowContext};
+use crate::assets::Assets;
+use crate::atlas::Atlas;
+use crate::camera::Camera;
+use crate::character::{Appearance, Character, Equipment};
 use crate::common::{Size, Position};
-use crate::tile::{TileId, TILE_SIZE};
-use crate::world::World;
+use crate::dropped_item::DroppedItems;
+use crate::font::{Align, Font};
+use crate::game_loop::{GameLoop, GameState};
+use crate::interaction::Interaction;
+use crate::inventory_screen::InventoryScreen;
+use crate::item::ItemAtlases;
+use crate::tile::{TileAtlases, TileId};
+use crate::lighting::{LightOverlay, Lighting};
+use crate::liquid::{LiquidAtlases, LiquidSim};
+use crate::npc::{NpcAtlases, Npcs};
+use crate::player::Player;
+use crate::ui::{Ui, TEXT_COLOR};
 use crate::viewport::Viewport;
+use crate::wall::WallAtlases;
+use crate::world::World;
+use crate::worldgen::WorldGen;
+
+const WORLD_FILE: &str = "world.wld";
+const TICKS_PER_SECOND: u32 = 60;
+// frame cap for displays without a known refresh rate
+const DEFAULT_FPS: i32 = 60;
+// distance of the frame rate from the top right corner, in pixels
+const FPS_MARGIN: i32 = 10;
+// the player's health bar sits under the frame rate
+const HEALTH_BAR_SIZE: (u32, u32) = (120, 12);
+const HEALTH_COLOR: Color = Color { r: 220, g: 40, b: 50, a: 255 };
+
+// command line: `[seed] [--headless] [--frames N] [--out DIR] [--size WxH] [--pack-atlas] [--watch]`
+struct Options {
+    // generate a new world from this seed instead of loading the saved one
+    seed: Option<u64>,
+    // render to PNG files instead of a window; for machines without a display
+    headless: bool,
+    frames: u32,
+    out_dir: String,
+    size: Size,
+    // pack the sprites into texture atlas pages and quit
+    pack_atlas: bool,
+    // load images again when their files change, for working on them while the game runs
+    watch: bool,
+}
+
+fn parse_options(args: impl Iterator<Item=String>) -> Result<Options, String> {
+    let mut options = Options {
+        seed: None,
+        headless: false,
+        frames: 1,
+        out_dir: "frames".to_string(),
+        size: Size { w: 800, h: 600 },
+        pack_atlas: false,
+        watch: false,
+    };
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "--headless" => options.headless = true,
+            "--pack-atlas" => options.pack_atlas = true,
+            "--watch" => options.watch = true,
+            "--frames" => {
+                options.frames = value("--frames")?.parse()
+                    .map_err(|_| "The frame count must be an unsigned integer".to_string())?;
+            }
+            "--out" => options.out_dir = value("--out")?,
+            "--size" => {
+                let size = value("--size")?;
+                let parsed = size.split_once('x').and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+                let (w, h) = parsed.ok_or_else(|| format!("The size must look like 800x600, not {}", size))?;
+                options.size = Size { w, h };
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown argument {}", arg)),
+            _ => {
+                options.seed = Some(arg.parse()
+                    .map_err(|_| "The world seed must be an unsigned integer".to_string())?);
+            }
+        }
+    }
+    Ok(options)
+}
+
+// where the player starts: on the ground in the middle of the world
+fn start_point(world: &World) -> (usize, usize) {
+    let x = world.width() / 2;
+    let y = (0..world.height()).find(|&y| world.get_tile(x, y).unwrap().get_id() != TileId::NOTHING).unwrap_or(0);
+    (x, y)
+}
 
 fn main() {
+    let options = parse_options(std::env::args().skip(1))
+        .unwrap_or_else(|msg| panic!("ERROR: {}", msg));
     // init graphics stuff
     let sdl_context: Sdl = sdl2::init().unwrap();
     let _image_context = sdl2::image::init(InitFlag::PNG);
+    if options.pack_atlas {
+        let manifest = atlas::pack_sprites().unwrap();
+        println!("Packed {} sprites onto {} pages in {}", manifest.regions.len(), manifest.pages.len(), atlas::ATLAS_DIR);
+        return;
+    }
+    // a seed on the command line generates a new world; otherwise the
+    // last saved world is loaded, or a world with a random seed is made if there is none
+    let w = 1000;
+    let h = 1000;
+    let loaded = match options.seed {
+        None if std::path::Path::new(WORLD_FILE).exists() => save::load_world(WORLD_FILE).map_err(|msg| {
+            // moved aside so that saving the new world does not overwrite it
+            let kept = format!("{}.corrupt", WORLD_FILE);
+            println!("{}; keeping it as {} and generating a new world", msg, kept);
+            if let Err(e) = std::fs::rename(WORLD_FILE, &kept) {
+                println!("Could not move {} to {}: {}", WORLD_FILE, kept, e);
+            }
+        }).ok(),
+        _ => None,
+    };
+    let mut my_world = match loaded {
+        Some(world) => world,
+        None => {
+            let seed = options.seed.unwrap_or_else(rand::random);
+            println!("World seed: {}", seed);
+            WorldGen::new(seed).generate(w, h).unwrap()
+        }
+    };
+    let (start_x, start_y) = start_point(&my_world);
+    let mut world_view = Viewport::new();
+    world_view.set_pos(Position { x: start_x as i32, y: start_y as i32 });
+    if options.headless {
+        // the world is only looked at, so it is not saved either
+        let player = Player::new(start_x as f64 + 0.5, start_y as f64);
+        headless::run(&mut my_world, &mut world_view, &player, options.size, options.frames, &options.out_dir).unwrap();
+        return;
+    }
     let video_subsystem: VideoSubsystem = sdl_context.video().unwrap();
     // create window
-    let window: Window = video_subsystem.window("Game", 800, 600)
+    let window: Window = video_subsystem.window("Game", options.size.w, options.size.h)
         .position_centered()
         .resizable()
         .build()
         .unwrap();
-    let fps = window.display_mode().unwrap().refresh_rate;
+    // displays that do not know their refresh rate report 0
+    let fps = match window.display_mode().unwrap().refresh_rate {
+        rate if rate > 0 => rate,
+        _ => DEFAULT_FPS,
+    };
     // used for direct screen drawing or drawing to a texture
-    let mut canvas = window.into_canvas().target_texture().build().unwrap();
+    let canvas = window.into_canvas().target_texture().build().unwrap();
     // send this anywhere textures are needed, textures cannot outlive it!
     let texture_creator = canvas.texture_creator();
     // all tile rendering will use this collection as a reference
     // prevent excessive file I/O and texture copying
-    let tile_atlases: EnumMap<tile::TileId, Sprite> = tile::load_tile_atlases(&texture_creator).unwrap();
-    // provides events
-    let mut event_pump = sdl_context.event_pump().unwrap();
-    // window size in pixels
-    let mut size: (u32, u32) = (canvas.window().size().0, canvas.window().size().1);
-    // fps is the maximum frame rate; each frame will be compared to this value and will hang the
-    // render thread if necessary to avoid going over this frame rate
-    let wait_time = std::time::Duration::from_nanos((1000000000i32 / fps) as u64);
-    // statistics
-    let mut total_frame_time: u128 = 0;
-    let mut num_frames: u128 = 0;
-    // define things here
-    let w = 1000;
-    let h = 1000;
-    let mut my_world = World::new(w, h);
-    for i in 0usize..w {
-        for j in 0usize..h {
-            if j == 3 {
-                my_world.get_tile_mut(i, j).unwrap().set_id(TileId::Grass);
-            } else if j > 3 {
-                my_world.get_tile_mut(i, j).unwrap().set_id(TileId::Dirt);
-            }
-        }
+    let mut assets = Assets::new(&texture_creator, Atlas::load_packed(&texture_creator)).unwrap();
+    assets.set_watching(options.watch);
+    let tile_atlases = tile::load_tile_atlases(&mut assets).unwrap();
+    let wall_atlases = wall::load_wall_atlases(&mut assets).unwrap();
+    let liquid_atlases = liquid::load_liquid_atlases(&mut assets).unwrap();
+    let item_atlases = item::load_item_atlases(&mut assets).unwrap();
+    let npc_atlases = npc::load_npc_atlases(&mut assets).unwrap();
+    let character = Character::load(&mut assets, &Appearance::new(), &Equipment::new());
+    let interaction = Interaction::new(&mut assets);
+    let font = Font::load_from_file(&texture_creator, font::DEFAULT_FONT).or_else(|msg| {
+        println!("{}", msg);
+        Font::builtin(&texture_creator)
+    }).unwrap();
+    let (width, height) = canvas.window().size();
+    world_view.set_output_dimensions(Size { w: width, h: height });
+    let mut game = Game {
+        canvas,
+        // provides events
+        event_pump: sdl_context.event_pump().unwrap(),
+        assets,
+        tile_atlases: &tile_atlases,
+        wall_atlases: &wall_atlases,
+        liquid_atlases: &liquid_atlases,
+        character: &character,
+        item_atlases: &item_atlases,
+        npc_atlases: &npc_atlases,
+        player: Player::new(start_x as f64 + 0.5, start_y as f64),
+        interaction,
+        drops: DroppedItems::new(),
+        npcs: Npcs::new(),
+        ui: Ui::new(font),
+        inventory_screen: InventoryScreen::new(),
+        frames_counted: 0,
+        fps_since: Instant::now(),
+        fps_text: String::new(),
+        camera: Camera::new(&world_view),
+        prev_view: world_view.clone(),
+        world_view,
+        liquids: LiquidSim::new(),
+        lighting: Lighting::new(&my_world),
+        light_overlay: LightOverlay::new(&texture_creator),
+        world: my_world,
+    };
+    // the simulation ticks at a fixed rate; frames are capped at the display's refresh rate
+    let mut game_loop = GameLoop::new(TICKS_PER_SECOND);
+    game_loop.set_frame_cap(Some(Duration::from_secs(1) / fps as u32));
+    let program_start = Instant::now();
+    game_loop.run(&mut game).unwrap();
+    if let Err(msg) = save::save_world(&game.world, WORLD_FILE) {
+        println!("{}", msg);
     }
-    my_world.update_cached_neighbors();
-    let mut world_view = Viewport::new();
-    world_view.set_output_dimensions(Size { w: size.0, h: size.1 });
-    world_view.set_pos(Position { x: 500, y: 3 });
-    // used for timing and average frame rate calculations
-    let program_start = std::time::Instant::now();
-    'running: loop {
-        let frame_start = std::time::Instant::now();
+    let seconds = program_start.elapsed().as_secs_f64();
+    println!("Average frame time: {:?} (ideal upper bound: {} ms)",
+             game_loop.average_frame_time(), 1000f64 / fps as f64);
+    println!("Average frame rate: {:.1} (ideal {}), {} slow frames",
+             game_loop.get_frames() as f64 / seconds, fps, game_loop.get_slow_frames());
+    println!("Average tick rate: {:.1} (ideal {})", game_loop.get_ticks() as f64 / seconds, TICKS_PER_SECOND);
+}
+
+// everything the window shows, driven by `GameLoop`; textures live for 'tc and the collections
+// holding them for 'a
+struct Game<'a, 'tc> {
+    canvas: WindowCanvas,
+    event_pump: EventPump,
+    assets: Assets<'tc, WindowContext>,
+    tile_atlases: &'a TileAtlases<'tc>,
+    wall_atlases: &'a WallAtlases<'tc>,
+    liquid_atlases: &'a LiquidAtlases<'tc>,
+    character: &'a Character<'tc>,
+    item_atlases: &'a ItemAtlases<'tc>,
+    npc_atlases: &'a NpcAtlases<'tc>,
+    world: World,
+    player: Player,
+    interaction: Interaction<'tc>,
+    // items lying in the world
+    drops: DroppedItems,
+    npcs: Npcs,
+    ui: Ui<'tc>,
+    inventory_screen: InventoryScreen,
+    // frames drawn since `fps_since`, for the frame rate shown in the corner
+    frames_counted: u32,
+    fps_since: Instant,
+    fps_text: String,
+    world_view: Viewport,
+    // the view as it was before the last tick, for interpolating between ticks
+    prev_view: Viewport,
+    camera: Camera,
+    liquids: LiquidSim,
+    lighting: Lighting,
+    light_overlay: LightOverlay<'tc, WindowContext>,
+}
+
+impl<'a, 'tc> GameState for Game<'a, 'tc> {
+    fn begin_frame(&mut self) -> bool {
+        self.assets.reload_changed();
         // event handling yay!
-        for event in event_pump.poll_iter() {
+        for event in self.event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } => {
-                    break 'running;
+                    return false;
+                }
+                Event::Window { win_event: WindowEvent::Resized(w, h), .. } => {
+                    let size = Size { w: w as u32, h: h as u32 };
+                    self.world_view.set_output_dimensions(size);
+                    self.prev_view.set_output_dimensions(size);
                 }
-                Event::Window { timestamp: _, window_id: _, win_event: e } => {
-                    if let WindowEvent::Resized(w, h) = e {
-                        size = (w as u32, h as u32);
+                _ => {
+                    self.inventory_screen.handle_event(&event);
+                    // clicks on the ui do not reach the world
+                    if !self.ui.handle_event(&event) {
+                        self.player.handle_event(&event);
+                        self.interaction.handle_event(&event);
+                        self.camera.handle_event(&event, &mut self.world_view, &mut self.prev_view);
                     }
                 }
-                _ => {}
             }
         }
+        true
+    }
+    fn update(&mut self, dt: f64) {
+        self.prev_view = self.world_view.clone();
+        self.player.update(&self.world, &self.npcs, dt);
+        self.camera.set_follow(Some(self.player.get_body().center()));
+        self.camera.update(dt, &mut self.world_view, &self.world);
+        self.interaction.update(dt, &mut self.world, &self.world_view, &mut self.player, &mut self.drops,
+                                &mut self.npcs);
+        let body = self.player.get_body().clone();
+        self.drops.update(&self.world, dt, &body, self.player.get_inventory_mut());
+        self.npcs.update(&self.world, dt, &body, self.npc_atlases);
+        self.liquids.step(&mut self.world);
+    }
+    fn render(&mut self, alpha: f64) -> Result<(), String> {
+        self.lighting.update(&mut self.world);
+        let (x0, y0) = self.prev_view.get_center();
+        let (x1, y1) = self.world_view.get_center();
+        let mut view = self.world_view.clone();
+        view.set_center(x0 + (x1 - x0) * alpha, y0 + (y1 - y0) * alpha);
+        view.set_zoom(self.prev_view.get_zoom() + (self.world_view.get_zoom() - self.prev_view.get_zoom()) * alpha);
         // background color
-        canvas.set_draw_color(Color::RGB(255, 255, 255));
-        canvas.clear();
+        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
+        self.canvas.clear();
         // Rendering code (each frame)
-        for i in 0usize..w {
-            for j in 0usize..h {
-                my_world.get_tile(i, j).unwrap().render(&tile_atlases, &mut canvas, Rect::new(i as i32 * TILE_SIZE as i32, j as i32 * TILE_SIZE as i32, TILE_SIZE, TILE_SIZE)).unwrap();
-            }
+        view.render(&mut self.canvas, &self.world, self.tile_atlases, self.wall_atlases, self.liquid_atlases)?;
+        self.drops.render(self.item_atlases, &mut self.canvas, &view, alpha)?;
+        self.npcs.render(self.npc_atlases, &mut self.canvas, &view, alpha)?;
+        self.player.render(self.character, &mut self.canvas, &view, alpha)?;
+        self.light_overlay.render(&mut self.canvas, &view, &self.world, self.lighting.get_map())?;
+        self.interaction.render(&mut self.canvas, &view, &self.world, self.player.get_body())?;
+        self.ui.begin_frame(&mut self.canvas);
+        self.inventory_screen.render(&mut self.ui, &mut self.canvas, &mut self.player, &self.world, &mut self.drops,
+                                     self.item_atlases)?;
+        self.frames_counted += 1;
+        let elapsed = self.fps_since.elapsed().as_secs_f64();
+        if elapsed >= 1.0 {
+            self.fps_text = format!("{:.0} fps", f64::from(self.frames_counted) / elapsed);
+            self.frames_counted = 0;
+            self.fps_since = Instant::now();
         }
+        let (width, height) = self.canvas.output_size()?;
+        let corner = Rect::new(0, FPS_MARGIN, (width as i32 - FPS_MARGIN).max(1) as u32, height);
+        self.ui.text(&mut self.canvas, corner, &self.fps_text, TEXT_COLOR, Align::Right)?;
+        let below = FPS_MARGIN + self.ui.measure("0").h as i32 + FPS_MARGIN / 2;
+        let health_bar = Rect::new(width as i32 - FPS_MARGIN - HEALTH_BAR_SIZE.0 as i32, below, HEALTH_BAR_SIZE.0,
+                                   HEALTH_BAR_SIZE.1);
+        let health = f64::from(self.player.get_health()) / f64::from(player::MAX_HEALTH);
+        self.ui.bar(&mut self.canvas, health_bar, health, HEALTH_COLOR)?;
+        self.ui.end_frame(&mut self.canvas)?;
         // Finished
-        canvas.present();
-        // Frame rate stabilization, never go above `fps`
-        let frame_time = std::time::Instant::now() - frame_start;
-        total_frame_time += frame_time.as_nanos();
-        num_frames += 1;
-        if frame_time < wait_time {
-            std::thread::sleep(wait_time - frame_time);
-        } else {
-            println!("frame took too long; presenting next ASAP\n\
-            ({:?} > {:?})", frame_time, wait_time);
-        }
+        self.canvas.present();
+        Ok(())
     }
-    println!("Average frame time: {} ms (ideal upper bound: {} ms)",
-             (total_frame_time as f64) / (num_frames as f64) / 1000000f64, 1000f64 / fps as f64);
-    println!("Average frame rate: {:?} (ideal {})",
-             (num_frames as f64) / (std::time::Instant::now() - program_start).as_nanos() as f64 * 1000000000f64, fps);
 }
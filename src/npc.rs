@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use sdl2::render::{Canvas, RenderTarget};
+use crate::animated_sprite::AnimatedSprite;
+use crate::animation::AnimationController;
+use crate::assets::{AnimationHandle, Assets};
+use crate::common::Size;
+use crate::entity::Body;
+use crate::npc_ai::{AiKind, AiState};
+use crate::npc_registry::{NpcInfo, NPC_REGISTRY};
+use crate::tile::TILE_SIZE;
+use crate::viewport::Viewport;
+use crate::world::World;
+
+// NPCs further than this from the target are dropped, in tiles
+const DESPAWN_DISTANCE: f64 = 90.0;
+// seconds between spawn attempts
+const SPAWN_INTERVAL: f64 = 4.0;
+const MAX_NPCS: usize = 6;
+// how far to either side of the target NPCs appear, in tiles; off screen, but not so far that
+// they take ages to arrive
+const SPAWN_DISTANCE: (f64, f64) = (30.0, 50.0);
+// flyers appear this far above the ground and worms this far below it, in tiles
+const FLY_HEIGHT: (f64, f64) = (6.0, 12.0);
+const BURROW_DEPTH: (f64, f64) = (8.0, 14.0);
+// slower than this, NPCs count as standing still, in tiles per second
+const MIN_SPEED: f64 = 0.1;
+// speed a hit sends an NPC away from the player, in tiles per second
+const KNOCKBACK: (f64, f64) = (8.0, 8.0);
+
+// numeric NPC id; what each id means comes from the NPC registry
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+#[derive(Hash)]
+pub struct NpcId(pub u16);
+
+impl NpcId {
+    pub fn info(self) -> &'static NpcInfo {
+        NPC_REGISTRY.get(self)
+    }
+}
+
+impl From<NpcId> for u16 {
+    fn from(id: NpcId) -> u16 {
+        id.0
+    }
+}
+
+// the way a sheet's frames face
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub enum Facing {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl Facing {
+    pub fn from_name(name: &str) -> Option<Facing> {
+        match name {
+            "left" => Some(Facing::Left),
+            "right" => Some(Facing::Right),
+            "up" => Some(Facing::Up),
+            "down" => Some(Facing::Down),
+            _ => None,
+        }
+    }
+    // in degrees clockwise from the right, like headings
+    pub fn angle(self) -> f64 {
+        match self {
+            Facing::Right => 0.0,
+            Facing::Down => 90.0,
+            Facing::Left => 180.0,
+            Facing::Up => 270.0,
+        }
+    }
+}
+
+// a creature in the world
+pub struct Npc {
+    id: NpcId,
+    body: Body,
+    prev_pos: (f64, f64),
+    health: u32,
+    ai: AiState,
+    animation: AnimationController,
+    facing_right: bool,
+    // the way it last moved, in degrees clockwise from the right
+    heading: f64,
+}
+
+impl Npc {
+    pub fn is_alive(&self) -> bool {
+        self.health > 0
+    }
+    // takes `amount` off its health; dead NPCs are removed on the next update
+    pub fn hurt(&mut self, amount: u32) {
+        self.health = self.health.saturating_sub(amount);
+    }
+    // whether `body` touches the NPC or any of its segments
+    pub fn touches(&self, body: &Body) -> bool {
+        if self.body.overlaps(body) {
+            return true;
+        }
+        match &self.ai {
+            AiState::Worm { segments } => segments.iter().any(|s| {
+                Body::new(s.x - self.body.w / 2.0, s.y - self.body.h / 2.0, self.body.w, self.body.h).overlaps(body)
+            }),
+            _ => false,
+        }
+    }
+    // the clip that fits what it is doing, and how fast to play it
+    fn clip(&self, info: &NpcInfo) -> (&'static str, f64) {
+        match info.ai {
+            AiKind::Slime if !self.body.get_on_ground() => ("hop", 1.0),
+            AiKind::Fighter if self.body.get_on_ground() && self.body.vx.abs() > MIN_SPEED => {
+                ("walk", self.body.vx.abs() / info.speed.max(MIN_SPEED))
+            }
+            AiKind::Slime | AiKind::Fighter => ("idle", 1.0),
+            AiKind::Flying | AiKind::Worm => ("fly", 1.0),
+        }
+    }
+}
+
+// the sheets a kind of NPC is drawn with
+pub struct NpcSprites<'a> {
+    pub sheet: AnimationHandle<'a>,
+    // a worm's body and tail segments
+    pub segments: Option<(AnimationHandle<'a>, AnimationHandle<'a>)>,
+}
+
+pub type NpcAtlases<'a> = HashMap<NpcId, NpcSprites<'a>>;
+
+pub fn load_npc_atlases<'tc, C>(assets: &mut Assets<'tc, C>) -> Result<NpcAtlases<'tc>, String> {
+    let mut npc_atlases = NpcAtlases::new();
+    // tints live on the shared sheets, so every NPC drawn with a sheet has to agree on one
+    let mut tints: HashMap<String, Option<Color>> = HashMap::new();
+    for info in NPC_REGISTRY.iter() {
+        let mut paths = vec![info.sprite.as_str()];
+        if let Some(worm) = &info.worm {
+            paths.push(&worm.segment);
+            paths.push(&worm.tail);
+        }
+        for path in paths {
+            if let Some(other) = tints.insert(path.to_string(), info.color) {
+                if other != info.color {
+                    return Err(format!("NPC {} tints {} differently from another NPC", info.name, path));
+                }
+            }
+        }
+        let mut sheet = |path: &str| {
+            let handle = assets.animated_sprite(path, info.frame_size, None);
+            if let Some(color) = info.color {
+                handle.get_mut().set_color_mod(color);
+            }
+            handle
+        };
+        let sprites = NpcSprites {
+            sheet: sheet(&info.sprite),
+            segments: info.worm.as_ref().map(|w| (sheet(&w.segment), sheet(&w.tail))),
+        };
+        npc_atlases.insert(info.id, sprites);
+    }
+    Ok(npc_atlases)
+}
+
+// every NPC in the world, and what brings new ones in
+pub struct Npcs {
+    npcs: Vec<Npc>,
+    // seconds until the next spawn attempt
+    spawn_timer: f64,
+    rng: StdRng,
+}
+
+impl Npcs {
+    pub fn new() -> Npcs {
+        Npcs {
+            npcs: Vec::new(),
+            spawn_timer: SPAWN_INTERVAL,
+            rng: StdRng::from_entropy(),
+        }
+    }
+    // adds an NPC of kind `id` centered on (x, y) in tile coordinates
+    pub fn spawn(&mut self, id: NpcId, x: f64, y: f64, atlases: &NpcAtlases) {
+        let info = id.info();
+        let (w, h) = info.size;
+        let body = Body::new(x - w / 2.0, y - h / 2.0, w, h);
+        let animation = match atlases.get(&id) {
+            Some(sprites) => sprites.sheet.get().animation_controller(),
+            None => AnimationController::new(),
+        };
+        self.npcs.push(Npc {
+            id,
+            prev_pos: (body.x, body.y),
+            ai: AiState::new(info, &body),
+            body,
+            health: info.health,
+            animation,
+            facing_right: false,
+            heading: info.facing.angle(),
+        });
+    }
+    // the most damage any NPC touching `body` deals, and the x of that NPC's center
+    pub fn contact_damage(&self, body: &Body) -> Option<(u32, f64)> {
+        self.npcs.iter()
+            .filter(|npc| npc.touches(body))
+            .map(|npc| (npc.id.info().damage, npc.body.center().0))
+            .max_by_key(|&(damage, _)| damage)
+    }
+    // hurts the NPC at the point (x, y) by `damage` and knocks it away from `from_x`; returns
+    // whether there was one to hit
+    pub fn hit_at(&mut self, x: f64, y: f64, damage: u32, from_x: f64) -> bool {
+        let point = Body::new(x, y, 0.0, 0.0);
+        let npc = match self.npcs.iter_mut().find(|npc| npc.is_alive() && npc.touches(&point)) {
+            Some(npc) => npc,
+            None => return false,
+        };
+        npc.hurt(damage);
+        let away = if npc.body.center().0 < from_x { -1.0 } else { 1.0 };
+        npc.body.vx = away * KNOCKBACK.0;
+        if !npc.id.info().ai.airborne() {
+            npc.body.vy = -KNOCKBACK.1;
+        }
+        true
+    }
+    // advances every NPC by `dt` seconds, all of them going after `target`; drops the dead and the
+    // far away, and now and then brings in a new one
+    pub fn update(&mut self, world: &World, dt: f64, target: &Body, atlases: &NpcAtlases) {
+        let (tx, ty) = target.center();
+        for npc in &mut self.npcs {
+            let info = npc.id.info();
+            npc.prev_pos = (npc.body.x, npc.body.y);
+            npc.ai.step(&mut npc.body, info, world, target, dt, &mut self.rng);
+            let (vx, vy) = (npc.body.vx, npc.body.vy);
+            if vx.abs() > MIN_SPEED {
+                npc.facing_right = vx > 0.0;
+            }
+            if vx.abs() > MIN_SPEED || vy.abs() > MIN_SPEED {
+                npc.heading = vy.atan2(vx).to_degrees();
+            }
+            let (clip, speed) = npc.clip(info);
+            // sheets without the clip just show their first frame
+            if npc.animation.play(clip).is_ok() {
+                npc.animation.set_speed(speed);
+            }
+            npc.animation.update(dt);
+        }
+        self.npcs.retain(|npc| {
+            let (x, y) = npc.body.center();
+            npc.is_alive() && (x - tx).abs() < DESPAWN_DISTANCE && (y - ty).abs() < DESPAWN_DISTANCE
+        });
+        self.spawn_timer -= dt;
+        if self.spawn_timer <= 0.0 {
+            self.spawn_timer = SPAWN_INTERVAL;
+            if self.npcs.len() < MAX_NPCS {
+                self.spawn_near(world, target, atlases);
+            }
+        }
+    }
+    // a random kind of NPC off to one side of `target`: on the ground, in the air above it or
+    // burrowed under it, as suits the kind
+    fn spawn_near(&mut self, world: &World, target: &Body, atlases: &NpcAtlases) {
+        let infos: Vec<&NpcInfo> = NPC_REGISTRY.iter().collect();
+        if infos.is_empty() {
+            return;
+        }
+        let info = infos[self.rng.gen_range(0, infos.len())];
+        let side = if self.rng.gen::<bool>() { 1.0 } else { -1.0 };
+        let x = target.center().0 + side * self.rng.gen_range(SPAWN_DISTANCE.0, SPAWN_DISTANCE.1);
+        if x < 0.0 || x >= world.width() as f64 {
+            return;
+        }
+        let surface = match (0..world.height()).find(|&y| world.get_tile(x as usize, y).unwrap().get_solid()) {
+            Some(y) => y as f64,
+            None => return,
+        };
+        let y = match info.ai {
+            AiKind::Flying => surface - self.rng.gen_range(FLY_HEIGHT.0, FLY_HEIGHT.1),
+            AiKind::Worm => surface + self.rng.gen_range(BURROW_DEPTH.0, BURROW_DEPTH.1),
+            _ => surface - info.size.1 / 2.0,
+        };
+        if y - info.size.1 / 2.0 >= 0.0 && y + info.size.1 / 2.0 <= world.height() as f64 {
+            self.spawn(info.id, x, y, atlases);
+        }
+    }
+    pub fn render<T: RenderTarget>(&self, atlases: &NpcAtlases, canvas: &mut Canvas<T>, view: &Viewport,
+                                   alpha: f64) -> Result<(), String> {
+        for npc in &self.npcs {
+            let info = npc.id.info();
+            let sprites = match atlases.get(&npc.id) {
+                Some(s) => s,
+                None => continue,
+            };
+            // the tail first, so that every segment lies under the one ahead of it
+            if let (AiState::Worm { segments }, Some((segment, tail))) = (&npc.ai, &sprites.segments) {
+                for (i, s) in segments.iter().enumerate().rev() {
+                    let sprite = if i + 1 == segments.len() { tail.get() } else { segment.get() };
+                    let at = (s.prev_pos.0 + (s.x - s.prev_pos.0) * alpha, s.prev_pos.1 + (s.y - s.prev_pos.1) * alpha);
+                    let (at, size) = placement(&sprite, view, at);
+                    let (angle, flip) = pose(info, npc.facing_right, s.angle);
+                    sprite.render_rotated(canvas, at, Some(size), (0, 0), angle, flip)?;
+                }
+            }
+            let x = npc.prev_pos.0 + (npc.body.x - npc.prev_pos.0) * alpha + npc.body.w / 2.0;
+            let y = npc.prev_pos.1 + (npc.body.y - npc.prev_pos.1) * alpha;
+            // the sheet's origin goes on the middle of the hitbox for things that do not stand, and
+            // on the middle of its bottom for things that do
+            let y = if info.ai.airborne() { y + npc.body.h / 2.0 } else { y + npc.body.h };
+            let sheet = sprites.sheet.get();
+            let (at, size) = placement(&sheet, view, (x, y));
+            let (angle, flip) = pose(info, npc.facing_right, npc.heading);
+            sheet.render_animation(canvas, at, Some(size), &npc.animation, angle, flip)?;
+        }
+        Ok(())
+    }
+}
+
+// the angle to turn a frame and whether to mirror it, for an NPC facing right or not and heading
+// `heading` degrees clockwise from the right
+fn pose(info: &NpcInfo, facing_right: bool, heading: f64) -> (f64, bool) {
+    let sideways = matches!(info.facing, Facing::Left | Facing::Right);
+    match (info.rotate, sideways) {
+        // mirrored to face the way it heads, then turned the rest of the way
+        (true, true) => {
+            let heading_right = heading.to_radians().cos() >= 0.0;
+            let base = if heading_right { 0.0 } else { 180.0 };
+            (heading - base, heading_right != (info.facing == Facing::Right))
+        }
+        (true, false) => (heading - info.facing.angle(), false),
+        (false, true) => (0.0, facing_right != (info.facing == Facing::Right)),
+        (false, false) => (0.0, false),
+    }
+}
+
+// the screen point for the origin of a frame of `sprite` at the point `at` in tile coordinates,
+// and the frame's size on screen when scaled like the tiles
+fn placement(sprite: &AnimatedSprite, view: &Viewport, at: (f64, f64)) -> (Point, Size) {
+    let scale = view.tile_pixels() / f64::from(TILE_SIZE);
+    let size = Size {
+        w: (sprite.frame_width() as f64 * scale).round() as u32,
+        h: (sprite.frame_height() as f64 * scale).round() as u32,
+    };
+    let (x, y) = view.point_to_screen(at.0, at.1);
+    (Point::new(x, y), size)
+}
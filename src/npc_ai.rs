@@ -0,0 +1,208 @@
+use rand::Rng;
+use crate::entity::Body;
+use crate::npc_registry::NpcInfo;
+use crate::world::World;
+
+// slows slimes sliding along the ground, in tiles per second squared
+const SLIME_FRICTION: f64 = 40.0;
+// seconds a slime rests between hops, at least and at most
+const HOP_WAIT: (f64, f64) = (1.0, 2.5);
+// how quickly fighters get up to speed, in tiles per second squared
+const FIGHTER_ACCEL: f64 = 12.0;
+// fighters this close to their target's x stop walking, in tiles
+const FIGHTER_REACH: f64 = 0.5;
+// seconds a fighter walks into a wall before jumping it
+const STUCK_TIME: f64 = 0.15;
+const FLY_ACCEL: f64 = 10.0;
+// what is left of a flyer's speed after hitting a tile
+const BOUNCE: f64 = 0.6;
+const WORM_ACCEL: f64 = 25.0;
+
+// how a kind of NPC moves
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub enum AiKind {
+    Slime,
+    Fighter,
+    Flying,
+    Worm,
+}
+
+impl AiKind {
+    pub fn from_name(name: &str) -> Option<AiKind> {
+        match name {
+            "slime" => Some(AiKind::Slime),
+            "fighter" => Some(AiKind::Fighter),
+            "flying" => Some(AiKind::Flying),
+            "worm" => Some(AiKind::Worm),
+            _ => None,
+        }
+    }
+    // whether it gets around without standing on anything
+    pub fn airborne(self) -> bool {
+        matches!(self, AiKind::Flying | AiKind::Worm)
+    }
+}
+
+// what an NPC's behaviour remembers between ticks
+pub enum AiState {
+    // seconds until the next hop
+    Slime { wait: f64 },
+    // seconds spent walking into a wall
+    Fighter { stuck: f64 },
+    Flying,
+    // head first, tail last
+    Worm { segments: Vec<Segment> },
+}
+
+impl AiState {
+    // a fresh state for `info`'s behaviour, with any worm segments trailing down from `body`
+    pub fn new(info: &NpcInfo, body: &Body) -> AiState {
+        match info.ai {
+            AiKind::Slime => AiState::Slime { wait: HOP_WAIT.0 },
+            AiKind::Fighter => AiState::Fighter { stuck: 0.0 },
+            AiKind::Flying => AiState::Flying,
+            AiKind::Worm => {
+                let (x, y) = body.center();
+                let (length, spacing) = info.worm.as_ref().map_or((0, 0.0), |w| (w.length, w.spacing));
+                let segments = (1..=length).map(|i| Segment::new(x, y + spacing * i as f64)).collect();
+                AiState::Worm { segments }
+            }
+        }
+    }
+    // moves `body` by its behaviour for `dt` seconds, going after `target`
+    pub fn step<R: Rng>(&mut self, body: &mut Body, info: &NpcInfo, world: &World, target: &Body, dt: f64,
+                        rng: &mut R) {
+        match self {
+            AiState::Slime { wait } => slime(body, wait, info, world, target, dt, rng),
+            AiState::Fighter { stuck } => fighter(body, stuck, info, world, target, dt),
+            AiState::Flying => flying(body, info, world, target, dt),
+            AiState::Worm { segments } => worm(body, segments, info, world, target, dt),
+        }
+    }
+}
+
+// a piece of a worm's body, dragged along by the piece ahead of it
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Segment {
+    // center, in tiles
+    pub x: f64,
+    pub y: f64,
+    pub prev_pos: (f64, f64),
+    // the way to the piece ahead, in degrees clockwise from the right
+    pub angle: f64,
+}
+
+impl Segment {
+    pub fn new(x: f64, y: f64) -> Segment {
+        Segment { x, y, prev_pos: (x, y), angle: 270.0 }
+    }
+    // moves up to `spacing` behind the point `ahead`; a segment already closer stays put, so a
+    // worm can coil
+    pub fn follow(&mut self, ahead: (f64, f64), spacing: f64) {
+        self.prev_pos = (self.x, self.y);
+        let (dx, dy) = (ahead.0 - self.x, ahead.1 - self.y);
+        let distance = (dx * dx + dy * dy).sqrt();
+        if distance < 1e-6 {
+            return;
+        }
+        self.angle = dy.atan2(dx).to_degrees();
+        if distance > spacing {
+            self.x = ahead.0 - dx / distance * spacing;
+            self.y = ahead.1 - dy / distance * spacing;
+        }
+    }
+}
+
+// rests on the ground, then hops at the target
+pub fn slime<R: Rng>(body: &mut Body, wait: &mut f64, info: &NpcInfo, world: &World, target: &Body, dt: f64,
+                     rng: &mut R) {
+    body.apply_gravity(dt);
+    if body.get_on_ground() {
+        let slowed = body.vx.abs() - SLIME_FRICTION * dt;
+        body.vx = body.vx.signum() * slowed.max(0.0);
+        *wait -= dt;
+        if *wait <= 0.0 {
+            body.vx = (target.center().0 - body.center().0).signum() * info.speed;
+            body.vy = -info.jump_speed;
+            *wait = rng.gen_range(HOP_WAIT.0, HOP_WAIT.1);
+        }
+    }
+    body.move_and_collide(world, dt, false);
+}
+
+// walks at the target, up ledges and over walls it cannot step up
+pub fn fighter(body: &mut Body, stuck: &mut f64, info: &NpcInfo, world: &World, target: &Body, dt: f64) {
+    let dx = target.center().0 - body.center().0;
+    let dir = if dx.abs() < FIGHTER_REACH { 0.0 } else { dx.signum() };
+    body.vx = approach(body.vx, dir * info.speed, FIGHTER_ACCEL * dt);
+    body.apply_gravity(dt);
+    let x_before = body.x;
+    body.move_and_collide(world, dt, true);
+    if dir != 0.0 && body.get_on_ground() && (body.x - x_before).abs() < 1e-6 {
+        *stuck += dt;
+    } else {
+        *stuck = 0.0;
+    }
+    if *stuck > STUCK_TIME {
+        body.vy = -info.jump_speed;
+        *stuck = 0.0;
+    }
+}
+
+// flies straight at the target, bouncing off tiles in the way
+pub fn flying(body: &mut Body, info: &NpcInfo, world: &World, target: &Body, dt: f64) {
+    steer(body, target, FLY_ACCEL * dt, info.speed);
+    let (vx, vy) = (body.vx, body.vy);
+    body.move_and_collide(world, dt, false);
+    if body.vx == 0.0 && vx != 0.0 {
+        body.vx = -vx * BOUNCE;
+    }
+    if body.vy == 0.0 && vy != 0.0 {
+        body.vy = -vy * BOUNCE;
+    }
+}
+
+// swims through solid tiles after the target and falls through open air, dragging its segments
+pub fn worm(body: &mut Body, segments: &mut [Segment], info: &NpcInfo, world: &World, target: &Body, dt: f64) {
+    if body.in_solid(world) {
+        steer(body, target, WORM_ACCEL * dt, info.speed);
+    } else {
+        body.apply_gravity(dt);
+    }
+    // tiles do not stop it, but the edges of the world do
+    body.x = (body.x + body.vx * dt).clamp(0.0, (world.width() as f64 - body.w).max(0.0));
+    body.y = (body.y + body.vy * dt).min(world.height() as f64 - body.h);
+    let spacing = info.worm.as_ref().map_or(1.0, |w| w.spacing);
+    let mut ahead = body.center();
+    for segment in segments.iter_mut() {
+        segment.follow(ahead, spacing);
+        ahead = (segment.x, segment.y);
+    }
+}
+
+// speeds `body` up by `dv` towards the target's center, to at most `max_speed`
+fn steer(body: &mut Body, target: &Body, dv: f64, max_speed: f64) {
+    let ((tx, ty), (x, y)) = (target.center(), body.center());
+    let (dx, dy) = (tx - x, ty - y);
+    let distance = (dx * dx + dy * dy).sqrt().max(1e-6);
+    body.vx += dx / distance * dv;
+    body.vy += dy / distance * dv;
+    let speed = (body.vx * body.vx + body.vy * body.vy).sqrt();
+    if speed > max_speed {
+        body.vx *= max_speed / speed;
+        body.vy *= max_speed / speed;
+    }
+}
+
+// `value` moved `step` closer to `goal`, without going past it
+fn approach(value: f64, goal: f64, step: f64) -> f64 {
+    if value < goal {
+        (value + step).min(goal)
+    } else {
+        (value - step).max(goal)
+    }
+}
@@ -0,0 +1,159 @@
+use sdl2::pixels::Color;
+use serde::Deserialize;
+use crate::npc::{Facing, NpcId};
+use crate::npc_ai::AiKind;
+use crate::registry::{self, Entry, Registry};
+
+pub const NPC_REGISTRY_FILE: &str = "data/npcs.toml";
+
+lazy_static! {
+    // every NPC the game knows about, read once from `NPC_REGISTRY_FILE`
+    pub static ref NPC_REGISTRY: NpcRegistry = NpcRegistry::load_from_file(NPC_REGISTRY_FILE)
+        .unwrap_or_else(|msg| panic!("ERROR: Could not load the NPC registry: {}", msg));
+}
+
+// everything about a kind of NPC that is not stored per NPC
+#[derive(Debug)]
+pub struct NpcInfo {
+    pub id: NpcId,
+    pub name: String,
+    pub sprite: String,
+    // frame size in pixels for sheets without a sidecar file
+    pub frame_size: Option<(usize, usize)>,
+    // hitbox in tiles
+    pub size: (f64, f64),
+    pub health: u32,
+    pub damage: u32,
+    pub ai: AiKind,
+    // in tiles per second
+    pub speed: f64,
+    pub jump_speed: f64,
+    // the way the sheet's frames face
+    pub facing: Facing,
+    // turn the frames to where the NPC is heading
+    pub rotate: bool,
+    pub color: Option<Color>,
+    pub worm: Option<WormInfo>,
+}
+
+// the trailing segments of a worm
+#[derive(Debug)]
+pub struct WormInfo {
+    pub segment: String,
+    pub tail: String,
+    // segments behind the head, tail included
+    pub length: usize,
+    // between segment centers, in tiles
+    pub spacing: f64,
+}
+
+// an `[[npc]]` table as written in the registry file
+#[derive(Deserialize)]
+struct NpcEntry {
+    id: u16,
+    name: String,
+    sprite: String,
+    frame_size: Option<[usize; 2]>,
+    size: [f64; 2],
+    health: u32,
+    damage: u32,
+    ai: String,
+    speed: f64,
+    #[serde(default = "default_jump_speed")]
+    jump_speed: f64,
+    #[serde(default = "default_facing")]
+    facing: String,
+    #[serde(default)]
+    rotate: bool,
+    color: Option<[u8; 3]>,
+    segments: Option<[String; 2]>,
+    length: Option<usize>,
+    #[serde(default = "default_segment_spacing")]
+    segment_spacing: f64,
+}
+
+fn default_jump_speed() -> f64 {
+    20.0
+}
+
+fn default_facing() -> String {
+    "left".to_string()
+}
+
+fn default_segment_spacing() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize)]
+struct RegistryFile {
+    #[serde(default)]
+    npc: Vec<NpcEntry>,
+}
+
+pub type NpcRegistry = Registry<NpcInfo>;
+
+impl Entry for NpcInfo {
+    type Id = NpcId;
+    const KIND: &'static str = "NPC";
+    fn id(&self) -> NpcId {
+        self.id
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Registry<NpcInfo> {
+    pub fn load_from_file(path: &str) -> Result<NpcRegistry, String> {
+        registry::load_file(path, NpcRegistry::parse)
+    }
+    pub fn parse(text: &str) -> Result<NpcRegistry, String> {
+        let file: RegistryFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        let mut registry = NpcRegistry::default();
+        for entry in &file.npc {
+            let ai = AiKind::from_name(&entry.ai)
+                .ok_or_else(|| format!("NPC {} has an unknown ai {}", entry.name, entry.ai))?;
+            let facing = Facing::from_name(&entry.facing)
+                .ok_or_else(|| format!("NPC {} has an unknown facing {}", entry.name, entry.facing))?;
+            if entry.size[0] <= 0.0 || entry.size[1] <= 0.0 {
+                return Err(format!("NPC {} needs a size above 0", entry.name));
+            }
+            if entry.health == 0 {
+                return Err(format!("NPC {} has a health of 0", entry.name));
+            }
+            let worm = match (&entry.segments, entry.length) {
+                (Some([segment, tail]), Some(length)) if ai == AiKind::Worm => {
+                    if length == 0 || entry.segment_spacing <= 0.0 {
+                        return Err(format!("NPC {} needs a length and segment_spacing above 0", entry.name));
+                    }
+                    Some(WormInfo {
+                        segment: segment.clone(),
+                        tail: tail.clone(),
+                        length,
+                        spacing: entry.segment_spacing,
+                    })
+                }
+                (None, None) if ai != AiKind::Worm => None,
+                _ if ai == AiKind::Worm => return Err(format!("Worm {} needs segments and a length", entry.name)),
+                _ => return Err(format!("NPC {} has segments but is not a worm", entry.name)),
+            };
+            registry.insert(NpcInfo {
+                id: NpcId(entry.id),
+                name: entry.name.clone(),
+                sprite: entry.sprite.clone(),
+                frame_size: entry.frame_size.map(|[w, h]| (w, h)),
+                size: (entry.size[0], entry.size[1]),
+                health: entry.health,
+                damage: entry.damage,
+                ai,
+                speed: entry.speed,
+                jump_speed: entry.jump_speed,
+                facing,
+                rotate: entry.rotate,
+                color: entry.color.map(|[r, g, b]| Color::RGB(r, g, b)),
+                worm,
+            })?;
+        }
+        Ok(registry)
+    }
+}
@@ -0,0 +1,224 @@
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::render::{Canvas, RenderTarget};
+use crate::character::{Character, Pose};
+use crate::entity::Body;
+use crate::hotbar::HOTBAR_SLOTS;
+use crate::item::{Inventory, ItemStack};
+use crate::item_registry::ITEM_REGISTRY;
+use crate::npc::Npcs;
+use crate::tile::TILE_SIZE;
+use crate::viewport::Viewport;
+use crate::world::World;
+
+// the hitbox, in tiles; Terraria's 20 by 42 pixels
+const WIDTH: f64 = 1.25;
+const HEIGHT: f64 = 2.625;
+// in tiles per second (squared)
+const WALK_SPEED: f64 = 9.0;
+const GROUND_ACCEL: f64 = 70.0;
+const AIR_ACCEL: f64 = 35.0;
+const FRICTION: f64 = 50.0;
+const JUMP_SPEED: f64 = 24.0;
+// letting go of jump while still rising keeps this much of the upward speed, for short hops
+const JUMP_CUT: f64 = 0.5;
+// tiles walked per walk frame
+const WALK_STRIDE: f64 = 0.35;
+// seconds one swing of the arm takes when using an item
+const USE_TIME: f64 = 0.3;
+const INVENTORY_SLOTS: usize = 50;
+pub const MAX_HEALTH: u32 = 100;
+// seconds after a hit during which nothing else hurts
+const HURT_COOLDOWN: f64 = 0.67;
+// speed a hit sends the player away from what hit them, in tiles per second
+const KNOCKBACK: (f64, f64) = (10.0, 12.0);
+// blinks per second while nothing hurts
+const BLINK_RATE: f64 = 10.0;
+
+pub struct Player {
+    body: Body,
+    // where the body was before the last tick, for drawing between ticks
+    prev_pos: (f64, f64),
+    // held keys: left, right
+    held: [bool; 2],
+    // jump presses and releases since the last tick; holding jump does not keep bouncing
+    jump_pressed: bool,
+    jump_released: bool,
+    facing_right: bool,
+    // tiles walked, which picks the walk frame
+    walked: f64,
+    // seconds left of the current swing of the arm
+    using: f64,
+    inventory: Inventory,
+    // the hotbar slot holding the item in use
+    selected_slot: usize,
+    health: u32,
+    // seconds until the player can be hurt again
+    hurt_cooldown: f64,
+    // where the feet go when the player comes back after dying
+    spawn: (f64, f64),
+}
+
+impl Player {
+    // stands the player on the ground at (x, feet_y)
+    pub fn new(x: f64, feet_y: f64) -> Player {
+        let body = Body::new(x - WIDTH / 2.0, feet_y - HEIGHT, WIDTH, HEIGHT);
+        Player {
+            prev_pos: (body.x, body.y),
+            body,
+            held: [false; 2],
+            jump_pressed: false,
+            jump_released: false,
+            facing_right: true,
+            walked: 0.0,
+            using: 0.0,
+            inventory: Player::starting_inventory(),
+            selected_slot: 0,
+            health: MAX_HEALTH,
+            hurt_cooldown: 0.0,
+            spawn: (x, feet_y),
+        }
+    }
+    fn starting_inventory() -> Inventory {
+        let mut inventory = Inventory::new(INVENTORY_SLOTS);
+        for name in &["iron_pickaxe", "iron_broadsword"] {
+            if let Some(id) = ITEM_REGISTRY.id(name) {
+                inventory.add(ItemStack::new(id, 1));
+            }
+        }
+        inventory
+    }
+    pub fn get_inventory(&self) -> &Inventory {
+        &self.inventory
+    }
+    pub fn get_inventory_mut(&mut self) -> &mut Inventory {
+        &mut self.inventory
+    }
+    pub fn get_selected_slot(&self) -> usize {
+        self.selected_slot
+    }
+    pub fn set_selected_slot(&mut self, slot: usize) {
+        self.selected_slot = slot.min(HOTBAR_SLOTS - 1);
+    }
+    // the stack in the selected hotbar slot
+    pub fn active_item(&self) -> Option<&ItemStack> {
+        self.inventory.get(self.selected_slot)
+    }
+    pub fn get_body(&self) -> &Body {
+        &self.body
+    }
+    pub fn get_health(&self) -> u32 {
+        self.health
+    }
+    // takes `amount` off the player's health and knocks them away from `from_x`, unless they were
+    // hurt too recently; running out of health puts them back at their spawn point, healed
+    pub fn hurt(&mut self, amount: u32, from_x: f64) {
+        if amount == 0 || self.hurt_cooldown > 0.0 {
+            return;
+        }
+        self.health = self.health.saturating_sub(amount);
+        self.hurt_cooldown = HURT_COOLDOWN;
+        if self.health == 0 {
+            self.respawn();
+            return;
+        }
+        let away = if self.body.center().0 < from_x { -1.0 } else { 1.0 };
+        self.body.vx = away * KNOCKBACK.0;
+        self.body.vy = -KNOCKBACK.1;
+    }
+    fn respawn(&mut self) {
+        let (x, feet_y) = self.spawn;
+        self.body = Body::new(x - WIDTH / 2.0, feet_y - HEIGHT, WIDTH, HEIGHT);
+        self.prev_pos = (self.body.x, self.body.y);
+        self.health = MAX_HEALTH;
+    }
+    pub fn handle_event(&mut self, event: &Event) {
+        let (key, down, repeat) = match *event {
+            Event::KeyDown { keycode: Some(key), repeat, .. } => (key, true, repeat),
+            Event::KeyUp { keycode: Some(key), .. } => (key, false, false),
+            _ => return,
+        };
+        match key {
+            Keycode::A | Keycode::Left => self.held[0] = down,
+            Keycode::D | Keycode::Right => self.held[1] = down,
+            // 1 to 9 and then 0 pick the hotbar slots
+            Keycode::Num1 | Keycode::Num2 | Keycode::Num3 | Keycode::Num4 | Keycode::Num5 | Keycode::Num6
+            | Keycode::Num7 | Keycode::Num8 | Keycode::Num9 | Keycode::Num0 if down => {
+                let n = key as i32 - Keycode::Num1 as i32;
+                self.set_selected_slot(if n < 0 { 9 } else { n as usize });
+            }
+            Keycode::Space | Keycode::W | Keycode::Up if !repeat => {
+                if down {
+                    self.jump_pressed = true;
+                } else {
+                    self.jump_released = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    // moves the player by the held keys for `dt` seconds, and lets any NPC touching them hurt them
+    pub fn update(&mut self, world: &World, npcs: &Npcs, dt: f64) {
+        self.prev_pos = (self.body.x, self.body.y);
+        self.hurt_cooldown = (self.hurt_cooldown - dt).max(0.0);
+        self.using = (self.using - dt).max(0.0);
+        if let Some((damage, from_x)) = npcs.contact_damage(&self.body) {
+            self.hurt(damage, from_x);
+        }
+        let dir = f64::from(self.held[1] as u8) - f64::from(self.held[0] as u8);
+        let on_ground = self.body.get_on_ground();
+        if dir != 0.0 {
+            let accel = if on_ground { GROUND_ACCEL } else { AIR_ACCEL };
+            self.body.vx = (self.body.vx + dir * accel * dt).clamp(-WALK_SPEED, WALK_SPEED);
+            self.facing_right = dir > 0.0;
+        } else if on_ground {
+            // friction only slows the player down, it never turns them around
+            let slowed = self.body.vx.abs() - FRICTION * dt;
+            self.body.vx = self.body.vx.signum() * slowed.max(0.0);
+        }
+        if self.jump_pressed && on_ground {
+            self.body.vy = -JUMP_SPEED;
+        } else if self.jump_released && self.body.vy < 0.0 {
+            self.body.vy *= JUMP_CUT;
+        }
+        self.jump_pressed = false;
+        self.jump_released = false;
+        self.body.apply_gravity(dt);
+        let x_before = self.body.x;
+        self.body.move_and_collide(world, dt, true);
+        self.walked += (self.body.x - x_before).abs();
+    }
+    fn interpolated(&self, alpha: f64) -> (f64, f64) {
+        (self.prev_pos.0 + (self.body.x - self.prev_pos.0) * alpha,
+         self.prev_pos.1 + (self.body.y - self.prev_pos.1) * alpha)
+    }
+    // swings the arm with the active item; a swing in progress plays out first
+    pub fn use_item(&mut self) {
+        if self.using == 0.0 {
+            self.using = USE_TIME;
+        }
+    }
+    fn pose(&self) -> Pose {
+        if self.using > 0.0 {
+            Pose::Use(1.0 - self.using / USE_TIME)
+        } else if !self.body.get_on_ground() {
+            Pose::Jump
+        } else if self.body.vx.abs() > 0.1 {
+            Pose::Walk((self.walked / WALK_STRIDE) as u32)
+        } else {
+            Pose::Stand
+        }
+    }
+    pub fn render<T: RenderTarget>(&self, character: &Character, canvas: &mut Canvas<T>, view: &Viewport,
+                                   alpha: f64) -> Result<(), String> {
+        // blinks while it cannot be hurt
+        if (self.hurt_cooldown * BLINK_RATE) as u32 % 2 == 1 {
+            return Ok(());
+        }
+        let (x, y) = self.interpolated(alpha);
+        // the feet stand on the middle of the hitbox's bottom edge
+        let scale = view.tile_pixels() / f64::from(TILE_SIZE);
+        let (feet_x, feet_y) = view.point_to_screen(x + WIDTH / 2.0, y + HEIGHT);
+        character.render(canvas, feet_x, feet_y, scale, self.pose(), self.facing_right)
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::fs;
+
+// something a registry holds, looked up by its numeric id or its unique name
+pub trait Entry {
+    type Id: Copy + Into<u16>;
+    // what the entries are called in error messages, e.g. "Tile"
+    const KIND: &'static str;
+    fn id(&self) -> Self::Id;
+    fn name(&self) -> &str;
+}
+
+// every entry of one kind the game knows about
+pub struct Registry<T: Entry> {
+    // indexed by id; holes are ids that are not registered
+    entries: Vec<Option<T>>,
+    by_name: HashMap<String, T::Id>,
+}
+
+impl<T: Entry> Default for Registry<T> {
+    fn default() -> Registry<T> {
+        Registry {
+            entries: Vec::new(),
+            by_name: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Entry> Registry<T> {
+    // fails if the id or the name is already taken
+    pub fn insert(&mut self, info: T) -> Result<(), String> {
+        let id = info.id();
+        if self.contains(id) {
+            return Err(format!("{} id {} is used by both {} and {}", T::KIND, id.into(), self.get(id).name(),
+                               info.name()));
+        }
+        if self.by_name.contains_key(info.name()) {
+            return Err(format!("{} name {} is used more than once", T::KIND, info.name()));
+        }
+        let index = index(id);
+        if self.entries.len() <= index {
+            self.entries.resize_with(index + 1, || None);
+        }
+        self.by_name.insert(info.name().to_string(), id);
+        self.entries[index] = Some(info);
+        Ok(())
+    }
+    pub fn contains(&self, id: T::Id) -> bool {
+        matches!(self.entries.get(index(id)), Some(Some(_)))
+    }
+    // unregistered ids panic; ids only come from the registry itself or from validated saves
+    pub fn get(&self, id: T::Id) -> &T {
+        match self.entries.get(index(id)) {
+            Some(Some(info)) => info,
+            _ => panic!("ERROR: {} id {} is not registered", T::KIND, id.into()),
+        }
+    }
+    pub fn get_mut(&mut self, id: T::Id) -> Option<&mut T> {
+        self.entries.get_mut(index(id)).and_then(|e| e.as_mut())
+    }
+    pub fn id(&self, name: &str) -> Option<T::Id> {
+        self.by_name.get(name).cloned()
+    }
+    pub fn iter(&self) -> impl Iterator<Item=&T> {
+        self.entries.iter().filter_map(|e| e.as_ref())
+    }
+}
+
+fn index<I: Into<u16>>(id: I) -> usize {
+    usize::from(id.into())
+}
+
+// reads the registry file at `path` and hands its text to `parse`, naming the file in any error
+pub fn load_file<R>(path: &str, parse: impl FnOnce(&str) -> Result<R, String>) -> Result<R, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    parse(&text).map_err(|e| format!("{}: {}", path, e))
+}
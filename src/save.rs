@@ -0,0 +1,508 @@
+// World file layout (all integers little-endian):
+//
+//   magic    b"RGWF"
+//   version  u16
+//   width    u32
+//   height   u32
+//   sections until end of file, each one:
+//     tag      [u8; 4]
+//     length   u32, size of the payload in bytes
+//     checksum u32, Adler-32 of the payload
+//     payload
+//
+// Every layer of the world lives in its own section, so new layers can be added without
+// touching the ones that are already there. Unknown sections are skipped.
+//
+// "TILE" payload: runs covering every cell in row-major order, each one
+//     count u32, id u16, frame u32, solid u8, shape u8, part u8 x2
+// (the part is which cell of a piece of furniture this is, right and up from the cell it was
+// placed in)
+// "WALL" payload: runs covering every cell in row-major order, each one
+//     count u32, id u16, frame u32
+// "LIQD" payload: runs covering every cell in row-major order, each one
+//     count u32, kind u8, amount u8
+// (dry cells are written with kind 0)
+
+use std::fs;
+use crate::liquid::{Liquid, LiquidKind};
+use crate::tile::{Tile, TileId, TileShape};
+use crate::tile_registry::TILE_REGISTRY;
+use crate::wall::{WallId, WALL_REGISTRY};
+use crate::world::World;
+
+const MAGIC: &[u8; 4] = b"RGWF";
+// what is stored of a tile: id, frame, solid, shape and part
+type TileRecord = (TileId, u32, bool, TileShape, (u8, u8));
+pub const VERSION: u16 = 1;
+const TILE_SECTION: &[u8; 4] = b"TILE";
+const TILE_RUN_SIZE: usize = 4 + 2 + 4 + 1 + 1 + 2;
+const WALL_SECTION: &[u8; 4] = b"WALL";
+const WALL_RUN_SIZE: usize = 4 + 2 + 4;
+const LIQUID_SECTION: &[u8; 4] = b"LIQD";
+const LIQUID_RUN_SIZE: usize = 4 + 1 + 1;
+// the largest world a file may describe; anything bigger is taken as a corrupted header rather
+// than allocated
+const MAX_WORLD_SIDE: usize = 1 << 14;
+const MAX_WORLD_CELLS: usize = 1 << 26;
+
+pub fn save_world(world: &World, path: &str) -> Result<(), String> {
+    fs::write(path, encode_world(world))
+        .map_err(|e| format!("Could not write world file {}: {}", path, e))
+}
+
+pub fn load_world(path: &str) -> Result<World, String> {
+    let data = fs::read(path).map_err(|e| format!("Could not read world file {}: {}", path, e))?;
+    decode_world(&data).map_err(|e| format!("{}: {}", path, e))
+}
+
+pub fn encode_world(world: &World) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&(world.width() as u32).to_le_bytes());
+    out.extend_from_slice(&(world.height() as u32).to_le_bytes());
+    write_section(&mut out, TILE_SECTION, &encode_tiles(world));
+    write_section(&mut out, WALL_SECTION, &encode_walls(world));
+    write_section(&mut out, LIQUID_SECTION, &encode_liquids(world));
+    out
+}
+
+pub fn decode_world(data: &[u8]) -> Result<World, String> {
+    let mut reader = Reader::new(data);
+    if reader.take(4, "magic number")? != MAGIC {
+        return Err("Not a world file (bad magic number)".to_string());
+    }
+    let version = reader.u16("version")?;
+    if version > VERSION {
+        return Err(format!("World file version {} is newer than the newest supported version ({})",
+                           version, VERSION));
+    }
+    if version == 0 {
+        return Err("Corrupted world file: version 0 does not exist".to_string());
+    }
+    let width = reader.u32("width")? as usize;
+    let height = reader.u32("height")? as usize;
+    if width == 0 || height == 0 || width > MAX_WORLD_SIDE || height > MAX_WORLD_SIDE {
+        return Err(format!("Corrupted world file: a {}x{} world is not between 1 and {} tiles on a side",
+                           width, height, MAX_WORLD_SIDE));
+    }
+    cell_count(width, height)?;
+    let mut world = World::new(width, height);
+    let mut found_tiles = false;
+    while !reader.is_empty() {
+        let (tag, payload) = reader.section()?;
+        if tag == *TILE_SECTION {
+            decode_tiles(&mut world, payload)?;
+            found_tiles = true;
+        } else if tag == *WALL_SECTION {
+            decode_walls(&mut world, payload)?;
+        } else if tag == *LIQUID_SECTION {
+            decode_liquids(&mut world, payload)?;
+        }
+    }
+    if !found_tiles {
+        return Err("Corrupted world file: missing TILE section".to_string());
+    }
+    world.update_cached_neighbors();
+    Ok(world)
+}
+
+// cells in a `width` by `height` world, if it is small enough to load
+fn cell_count(width: usize, height: usize) -> Result<usize, String> {
+    match width.checked_mul(height) {
+        Some(cells) if cells <= MAX_WORLD_CELLS => Ok(cells),
+        _ => Err(format!("Corrupted world file: a {}x{} world has more than {} cells", width, height, MAX_WORLD_CELLS)),
+    }
+}
+
+fn write_section(out: &mut Vec<u8>, tag: &[u8; 4], payload: &[u8]) {
+    out.extend_from_slice(tag);
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(&adler32(payload).to_le_bytes());
+    out.extend_from_slice(payload);
+}
+
+// the parts of a tile that are stored; the neighbor cache is rebuilt on load
+fn tile_record(t: &Tile) -> TileRecord {
+    (t.get_id(), t.get_frame(), t.get_solid(), t.get_shape(), t.get_part())
+}
+
+fn encode_tiles(world: &World) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut run: Option<(TileRecord, u32)> = None;
+    for (_, _, t) in world.tiles_in(0, 0, world.width(), world.height()) {
+        let record = tile_record(t);
+        run = match run {
+            Some((r, count)) if r == record && count < u32::MAX => Some((r, count + 1)),
+            Some((r, count)) => {
+                write_tile_run(&mut out, r, count);
+                Some((record, 1))
+            }
+            None => Some((record, 1)),
+        };
+    }
+    if let Some((r, count)) = run {
+        write_tile_run(&mut out, r, count);
+    }
+    out
+}
+
+fn write_tile_run(out: &mut Vec<u8>, (id, frame, solid, shape, part): TileRecord, count: u32) {
+    out.extend_from_slice(&count.to_le_bytes());
+    out.extend_from_slice(&id.0.to_le_bytes());
+    out.extend_from_slice(&frame.to_le_bytes());
+    out.push(solid as u8);
+    out.push(shape as u8);
+    out.push(part.0);
+    out.push(part.1);
+}
+
+fn decode_tiles(world: &mut World, payload: &[u8]) -> Result<(), String> {
+    let (width, height) = (world.width(), world.height());
+    let total = cell_count(width, height)?;
+    let default = tile_record(&Tile::new());
+    if !payload.len().is_multiple_of(TILE_RUN_SIZE) {
+        return Err(format!("Corrupted world file: TILE section length {} is not a multiple of {}",
+                           payload.len(), TILE_RUN_SIZE));
+    }
+    let mut reader = Reader::new(payload);
+    let mut cell = 0usize;
+    while !reader.is_empty() {
+        let offset = reader.pos;
+        let count = reader.u32("tile run length")? as usize;
+        let raw_id = reader.u16("tile id")?;
+        let frame = reader.u32("tile frame")?;
+        let solid = match reader.u8("tile solid flag")? {
+            0 => false,
+            1 => true,
+            n => return Err(format!("Corrupted world file: solid flag {} at TILE offset {}", n, offset)),
+        };
+        let raw_shape = reader.u8("tile shape")?;
+        let shape = TileShape::from_u8(raw_shape)
+            .ok_or_else(|| format!("Corrupted world file: unknown tile shape {} at TILE offset {}", raw_shape, offset))?;
+        let part = (reader.u8("tile part")?, reader.u8("tile part")?);
+        let id = TileId(raw_id);
+        if !TILE_REGISTRY.contains(id) {
+            return Err(format!("Corrupted world file: unknown tile id {} at TILE offset {}", raw_id, offset));
+        }
+        if count == 0 || count > total - cell {
+            return Err(format!("Corrupted world file: tile run of {} at TILE offset {} overflows the \
+                                {}x{} world", count, offset, width, height));
+        }
+        // leaving default tiles alone keeps empty chunks unallocated
+        if (id, frame, solid, shape, part) != default {
+            for c in cell..cell + count {
+                let t = world.get_tile_mut(c % width, c / width)?;
+                t.set_id(id);
+                t.set_frame(frame);
+                t.set_solid(solid);
+                t.set_shape(shape);
+                t.set_part(part);
+            }
+        }
+        cell += count;
+    }
+    if cell != total {
+        return Err(format!("Corrupted world file: TILE section covers {} of {} tiles", cell, total));
+    }
+    Ok(())
+}
+
+fn encode_walls(world: &World) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut run: Option<((WallId, u32), u32)> = None;
+    for y in 0..world.height() {
+        for x in 0..world.width() {
+            let w = world.get_wall(x, y).unwrap();
+            let record = (w.get_id(), w.get_frame());
+            run = match run {
+                Some((r, count)) if r == record && count < u32::MAX => Some((r, count + 1)),
+                Some((r, count)) => {
+                    write_wall_run(&mut out, r, count);
+                    Some((record, 1))
+                }
+                None => Some((record, 1)),
+            };
+        }
+    }
+    if let Some((r, count)) = run {
+        write_wall_run(&mut out, r, count);
+    }
+    out
+}
+
+fn write_wall_run(out: &mut Vec<u8>, (id, frame): (WallId, u32), count: u32) {
+    out.extend_from_slice(&count.to_le_bytes());
+    out.extend_from_slice(&id.0.to_le_bytes());
+    out.extend_from_slice(&frame.to_le_bytes());
+}
+
+fn decode_walls(world: &mut World, payload: &[u8]) -> Result<(), String> {
+    let (width, height) = (world.width(), world.height());
+    let total = cell_count(width, height)?;
+    if !payload.len().is_multiple_of(WALL_RUN_SIZE) {
+        return Err(format!("Corrupted world file: WALL section length {} is not a multiple of {}",
+                           payload.len(), WALL_RUN_SIZE));
+    }
+    let mut reader = Reader::new(payload);
+    let mut cell = 0usize;
+    while !reader.is_empty() {
+        let offset = reader.pos;
+        let count = reader.u32("wall run length")? as usize;
+        let id = WallId(reader.u16("wall id")?);
+        let frame = reader.u32("wall frame")?;
+        if !WALL_REGISTRY.contains(id) {
+            return Err(format!("Corrupted world file: unknown wall id {} at WALL offset {}", id.0, offset));
+        }
+        if count == 0 || count > total - cell {
+            return Err(format!("Corrupted world file: wall run of {} at WALL offset {} overflows the \
+                                {}x{} world", count, offset, width, height));
+        }
+        if id != WallId::NOTHING {
+            for c in cell..cell + count {
+                let w = world.get_wall_mut(c % width, c / width)?;
+                w.set_id(id);
+                w.set_frame(frame);
+            }
+        }
+        cell += count;
+    }
+    if cell != total {
+        return Err(format!("Corrupted world file: WALL section covers {} of {} cells", cell, total));
+    }
+    Ok(())
+}
+
+// the parts of a liquid cell that are stored; dry cells all look the same
+fn liquid_record(l: &Liquid) -> (u8, u8) {
+    if l.is_empty() {
+        (0, 0)
+    } else {
+        (l.get_kind() as u8, l.get_amount())
+    }
+}
+
+fn encode_liquids(world: &World) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut run: Option<((u8, u8), u32)> = None;
+    for y in 0..world.height() {
+        for x in 0..world.width() {
+            let record = liquid_record(world.get_liquid(x, y).unwrap());
+            run = match run {
+                Some((r, count)) if r == record && count < u32::MAX => Some((r, count + 1)),
+                Some((r, count)) => {
+                    write_liquid_run(&mut out, r, count);
+                    Some((record, 1))
+                }
+                None => Some((record, 1)),
+            };
+        }
+    }
+    if let Some((r, count)) = run {
+        write_liquid_run(&mut out, r, count);
+    }
+    out
+}
+
+fn write_liquid_run(out: &mut Vec<u8>, (kind, amount): (u8, u8), count: u32) {
+    out.extend_from_slice(&count.to_le_bytes());
+    out.push(kind);
+    out.push(amount);
+}
+
+fn decode_liquids(world: &mut World, payload: &[u8]) -> Result<(), String> {
+    let (width, height) = (world.width(), world.height());
+    let total = cell_count(width, height)?;
+    if !payload.len().is_multiple_of(LIQUID_RUN_SIZE) {
+        return Err(format!("Corrupted world file: LIQD section length {} is not a multiple of {}",
+                           payload.len(), LIQUID_RUN_SIZE));
+    }
+    let mut reader = Reader::new(payload);
+    let mut cell = 0usize;
+    while !reader.is_empty() {
+        let offset = reader.pos;
+        let count = reader.u32("liquid run length")? as usize;
+        let raw_kind = reader.u8("liquid kind")?;
+        let amount = reader.u8("liquid amount")?;
+        let kind = LiquidKind::from_u8(raw_kind)
+            .ok_or_else(|| format!("Corrupted world file: unknown liquid kind {} at LIQD offset {}", raw_kind, offset))?;
+        if count == 0 || count > total - cell {
+            return Err(format!("Corrupted world file: liquid run of {} at LIQD offset {} overflows the \
+                                {}x{} world", count, offset, width, height));
+        }
+        if amount > 0 {
+            for c in cell..cell + count {
+                world.set_liquid(c % width, c / width, Liquid::of(kind, amount))?;
+            }
+        }
+        cell += count;
+    }
+    if cell != total {
+        return Err(format!("Corrupted world file: LIQD section covers {} of {} cells", cell, total));
+    }
+    Ok(())
+}
+
+// bounds-checked cursor over the file contents; every read names what it was looking for so that
+// truncation errors say exactly where the file ends
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data, pos: 0 }
+    }
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+    fn take(&mut self, n: usize, what: &str) -> Result<&'a [u8], String> {
+        if self.data.len() - self.pos < n {
+            return Err(format!("Truncated world file: needed {} bytes for the {} at offset {}, \
+                                but only {} remain", n, what, self.pos, self.data.len() - self.pos));
+        }
+        let bytes = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+    fn u8(&mut self, what: &str) -> Result<u8, String> {
+        Ok(self.take(1, what)?[0])
+    }
+    fn u16(&mut self, what: &str) -> Result<u16, String> {
+        let b = self.take(2, what)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+    fn u32(&mut self, what: &str) -> Result<u32, String> {
+        let b = self.take(4, what)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+    // returns (tag, payload) after verifying the payload checksum
+    fn section(&mut self) -> Result<([u8; 4], &'a [u8]), String> {
+        let t = self.take(4, "section tag")?;
+        let tag = [t[0], t[1], t[2], t[3]];
+        let name = String::from_utf8_lossy(&tag).into_owned();
+        let length = self.u32(&format!("{} section length", name))? as usize;
+        let checksum = self.u32(&format!("{} section checksum", name))?;
+        let payload = self.take(length, &format!("{} section payload", name))?;
+        if adler32(payload) != checksum {
+            return Err(format!("Corrupted world file: checksum mismatch in {} section", name));
+        }
+        Ok((tag, payload))
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 is the largest block that cannot overflow before reducing
+    for block in data.chunks(5552) {
+        for &byte in block {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a header for a `width` by `height` world with no sections after it
+    fn header(width: u32, height: u32) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn oversized_headers_are_rejected() {
+        for &(w, h) in &[(0, 10), (10, 0), (u32::MAX, u32::MAX), (1 << 14, 1 << 14), (1 << 20, 1)] {
+            let msg = decode_world(&header(w, h)).err().unwrap();
+            assert!(msg.starts_with("Corrupted world file"), "{}", msg);
+        }
+    }
+
+    // a world with something different in most cells of every layer
+    fn varied_world() -> World {
+        let mut world = World::new(40, 30);
+        let tiles: Vec<TileId> = ["dirt", "stone", "grass", "iron_ore"].iter()
+            .map(|name| TILE_REGISTRY.id(name).unwrap())
+            .collect();
+        let walls: Vec<WallId> = ["dirt_wall", "stone_wall"].iter().map(|name| WALL_REGISTRY.id(name).unwrap()).collect();
+        for y in 10..30 {
+            for x in 0..40 {
+                let n = x * 7 + y * 3;
+                let id = tiles[n % tiles.len()];
+                let t = world.get_tile_mut(x, y).unwrap();
+                t.set_id(id);
+                t.set_frame((n % 3) as u32);
+                t.set_solid(n % 5 != 0);
+                t.set_shape(TileShape::ALL[n % TileShape::ALL.len()]);
+                let w = world.get_wall_mut(x, y).unwrap();
+                w.set_id(walls[n % walls.len()]);
+                w.set_frame((n % 4) as u32);
+            }
+        }
+        let forge = TILE_REGISTRY.id("adamantite_forge").unwrap();
+        for (i, j) in [(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)].iter().cloned() {
+            let t = world.get_tile_mut(5 + usize::from(i), 9 - usize::from(j)).unwrap();
+            t.set_id(forge);
+            t.set_part((i, j));
+        }
+        for x in 10..40 {
+            world.set_liquid(x, 8, Liquid::of(LiquidKind::ALL[x % LiquidKind::ALL.len()], (x * 9) as u8)).unwrap();
+        }
+        world
+    }
+
+    #[test]
+    fn worlds_survive_a_round_trip() {
+        let world = varied_world();
+        let loaded = decode_world(&encode_world(&world)).unwrap();
+        assert_eq!((loaded.width(), loaded.height()), (world.width(), world.height()));
+        for y in 0..world.height() {
+            for x in 0..world.width() {
+                let (a, b) = (world.get_tile(x, y).unwrap(), loaded.get_tile(x, y).unwrap());
+                assert_eq!(tile_record(a), tile_record(b), "tile at ({}, {})", x, y);
+                let (a, b) = (world.get_wall(x, y).unwrap(), loaded.get_wall(x, y).unwrap());
+                assert_eq!((a.get_id(), a.get_frame()), (b.get_id(), b.get_frame()), "wall at ({}, {})", x, y);
+                let (a, b) = (world.get_liquid(x, y).unwrap(), loaded.get_liquid(x, y).unwrap());
+                assert_eq!(liquid_record(a), liquid_record(b), "liquid at ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let data = encode_world(&varied_world());
+        // inside the header, inside the TILE section header and inside the TILE payload
+        for &end in &[3, 12, 20, 40] {
+            let msg = decode_world(&data[..end]).err().unwrap();
+            assert!(msg.starts_with("Truncated world file"), "{}", msg);
+        }
+    }
+
+    #[test]
+    fn corrupted_run_lengths_are_rejected() {
+        let mut data = header(4, 4);
+        let mut payload = Vec::new();
+        write_tile_run(&mut payload, tile_record(&Tile::new()), 17);
+        write_section(&mut data, TILE_SECTION, &payload);
+        let msg = decode_world(&data).err().unwrap();
+        assert!(msg.contains("tile run of 17"), "{}", msg);
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let mut data = header(4, 4);
+        data[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        let msg = decode_world(&data).err().unwrap();
+        assert!(msg.contains("newer than the newest supported version"), "{}", msg);
+    }
+}
@@ -1,36 +1,63 @@
+use std::cell::RefCell;
+use std::rc::Rc;
 use sdl2::rect::Rect;
 use sdl2::render::BlendMode;
 use sdl2::surface::Surface;
 use sdl2::render::Texture;
 use sdl2::image::LoadSurface;
-use sdl2::video::WindowContext;
 use sdl2::render::TextureCreator;
-use sdl2::render::WindowCanvas;
+use sdl2::render::{Canvas, RenderTarget};
 use sdl2::pixels::Color;
 use crate::common::Size;
 
+// a texture shared by several sprites, like an atlas page
+pub type SharedTexture<'a> = Rc<RefCell<Texture<'a>>>;
+
+#[derive(Default)]
 pub struct Sprite<'a> {
-    texture: Option<sdl2::render::Texture<'a>>,
+    texture: Option<SharedTexture<'a>>,
+    // the part of the texture that is this sprite, when it shares the texture with others
+    region: Option<Rect>,
     width: u32,
     height: u32,
+    // a shared texture is set up for whichever sprite draws from it next, so a region keeps its
+    // own color, alpha and blend mode
+    color_mod: Option<Color>,
+    alpha: Option<u8>,
+    blend_mode: Option<BlendMode>,
+    // stands in for an image that could not be loaded; clips draw the whole of it
+    placeholder: bool,
 }
 
-impl<'a> Default for Sprite<'a> {
-    fn default() -> Sprite<'a> {
+impl<'a> Sprite<'a> {
+    pub fn new() -> Sprite<'a> {
         Sprite {
             texture: None,
+            region: None,
             width: 0,
             height: 0,
+            color_mod: None,
+            alpha: None,
+            blend_mode: None,
+            placeholder: false,
         }
     }
-}
-
-impl<'a> Sprite<'a> {
-    pub fn new() -> Sprite<'a> {
+    // `region` of a texture other sprites draw from too; it acts like a texture of its own
+    pub fn from_region(texture: SharedTexture<'a>, region: Rect) -> Sprite<'a> {
         Sprite {
-            texture: None,
-            width: 0,
-            height: 0,
+            texture: Some(texture),
+            region: Some(region),
+            width: region.width(),
+            height: region.height(),
+            ..Sprite::new()
+        }
+    }
+    // all of `texture`, shown in place of a missing image at whatever size and clip that would have
+    pub fn placeholder(texture: SharedTexture<'a>) -> Sprite<'a> {
+        let query = texture.borrow().query();
+        Sprite {
+            placeholder: true,
+            ..Sprite::from_region(texture, Rect::new(0, 0, query.width, query.height))
         }
     }
     pub fn width(&self) -> u32 {
@@ -39,42 +66,73 @@ impl<'a> Sprite<'a> {
     pub fn height(&self) -> u32 {
         self.height
     }
-    pub fn load_from_file(&mut self, texture_creator: &'a TextureCreator<WindowContext>,
+    pub fn load_from_file<C>(&mut self, texture_creator: &'a TextureCreator<C>,
                           path: &str, color_key: Option<Color>) -> Result<(), String> {
-        self.texture = None;
-        let surface_result = Surface::from_file(path);
-        match surface_result {
-            Ok(_) => {
-                let mut surface: Surface = surface_result.unwrap();
-                if let Some(key) = color_key {
-                    if let Err(msg) = surface.set_color_key(true, key) {
-                        return Err(msg);
-                    }
-                }
-                self.width = surface.width();
-                self.height = surface.height();
-                self.texture = Some(texture_creator.create_texture_from_surface(surface).unwrap());
-                Ok(())
-            }
-            Err(msg) => Err(msg)
+        // a file that cannot be read leaves the sprite as it was
+        let mut surface = Surface::from_file(path)?;
+        if let Some(key) = color_key {
+            surface.set_color_key(true, key)?;
         }
+        self.load_from_surface(texture_creator, surface)
+    }
+    // for pixels made at runtime rather than read from a file
+    pub fn load_from_surface<C>(&mut self, texture_creator: &'a TextureCreator<C>,
+                                surface: Surface) -> Result<(), String> {
+        self.width = surface.width();
+        self.height = surface.height();
+        self.region = None;
+        self.placeholder = false;
+        let texture = texture_creator.create_texture_from_surface(surface).map_err(|e| e.to_string())?;
+        self.texture = Some(Rc::new(RefCell::new(texture)));
+        self.apply_mods();
+        Ok(())
     }
     pub fn set_color_mod(&mut self, color: Color) {
-        if let Some(t) = &mut self.texture {
-            t.set_color_mod(color.r, color.g, color.b);
-        }
+        self.color_mod = Some(color);
+        self.apply_mods();
     }
     pub fn set_blend_mode(&mut self, mode: BlendMode) {
-        if let Some(t) = &mut self.texture {
-            t.set_blend_mode(mode);
-        }
+        self.blend_mode = Some(mode);
+        self.apply_mods();
     }
     pub fn set_alpha(&mut self, alpha: u8) {
-        if let Some(t) = &mut self.texture {
-            t.set_alpha_mod(alpha);
+        self.alpha = Some(alpha);
+        self.apply_mods();
+    }
+    // sets the texture up for this sprite; regions put back the defaults for whatever they leave
+    // unset, since another region may have changed it
+    fn apply_mods(&self) {
+        let texture = match &self.texture {
+            Some(t) => t,
+            None => return,
+        };
+        let mut t = texture.borrow_mut();
+        let shared = self.region.is_some();
+        if let Some(c) = self.color_mod.or(if shared { Some(Color::RGB(255, 255, 255)) } else { None }) {
+            t.set_color_mod(c.r, c.g, c.b);
+        }
+        if let Some(a) = self.alpha.or(if shared { Some(255) } else { None }) {
+            t.set_alpha_mod(a);
+        }
+        if let Some(m) = self.blend_mode.or(if shared { Some(BlendMode::Blend) } else { None }) {
+            t.set_blend_mode(m);
         }
     }
-    pub fn render(&self, canvas: &mut sdl2::render::Canvas<sdl2::video::Window>, x: i32, y: i32, size: Option<Size>, clip: Option<Rect>) -> Result<(), String> {
+    // `clip` within the sprite as a rect of the texture
+    fn source(&self, clip: Option<Rect>) -> Option<Rect> {
+        match (self.region, clip) {
+            (Some(r), _) if self.placeholder => Some(r),
+            (Some(r), Some(c)) => Some(Rect::new(r.x() + c.x(), r.y() + c.y(), c.width(), c.height())),
+            (Some(r), None) => Some(r),
+            (None, c) => c,
+        }
+    }
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, x: i32, y: i32, size: Option<Size>, clip: Option<Rect>) -> Result<(), String> {
+        self.render_flipped(canvas, x, y, size, clip, false)
+    }
+    // `render`, mirrored left to right when `flip_horizontal` is set
+    pub fn render_flipped<T: RenderTarget>(&self, canvas: &mut Canvas<T>, x: i32, y: i32, size: Option<Size>,
+                                           clip: Option<Rect>, flip_horizontal: bool) -> Result<(), String> {
         let mut render_quad: Rect = Rect::new(x, y, self.width, self.height);
         if let Some(c) = clip {
             render_quad.set_width(c.width());
@@ -85,13 +143,20 @@ impl<'a> Sprite<'a> {
             render_quad.set_height(s.h);
         }
         match &self.texture {
-            Some(t) => canvas.copy(t, clip, render_quad),
+            Some(t) => {
+                if self.region.is_some() {
+                    self.apply_mods();
+                }
+                canvas.copy_ex(&t.borrow(), self.source(clip), render_quad, 0.0, None, flip_horizontal, false)
+            }
             None => Ok(())
         }
     }
-    pub fn render_to_texture<'b>(&'b self, canvas: &mut WindowCanvas, texture_creator: &'b TextureCreator<WindowContext>,
-                                 size: Option<Rect>, clip: Option<Rect>)
-                                 -> Result<Texture, String> {
+    #[allow(dead_code)]
+    pub fn render_to_texture<'b, T: RenderTarget, C>(&'b self, canvas: &mut Canvas<T>,
+                                                     texture_creator: &'b TextureCreator<C>,
+                                                     size: Option<Rect>, clip: Option<Rect>)
+                                                     -> Result<Texture<'b>, String> {
         let mut render_quad: Rect = Rect::new(0, 0, self.width, self.height);
         if let Some(c) = clip {
             render_quad.set_width(c.width());
@@ -111,8 +176,11 @@ impl<'a> Sprite<'a> {
         let mut texture: Texture = texture_creator.create_texture_target(None, texture_w, texture_h).unwrap();
         let result = canvas.with_texture_canvas(&mut texture, |texture_canvas| {
             texture_canvas.clear();
+            if self.region.is_some() {
+                self.apply_mods();
+            }
             let copy_result = match &self.texture {
-                Some(t) => texture_canvas.copy(t, clip, Rect::new(0, 0, texture_w, texture_h)),
+                Some(t) => texture_canvas.copy(&t.borrow(), self.source(clip), Rect::new(0, 0, texture_w, texture_h)),
                 None => Ok(())
             };
             match copy_result {
@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use crate::animation::{Clip, PlayMode};
+
+// A sheet's metadata sits next to it with the same name, ending in .toml instead of .png:
+//
+//   frames        how many frames the sheet holds
+//   columns       strips of frames side by side, each read top to bottom (default 1)
+//   frame_width   in pixels, leaving out padding (default: the sheet's width split into columns)
+//   frame_height  likewise (default: the sheet's height split into as many rows as the frames need)
+//   padding       empty pixels to the right of and below every frame (default 0)
+//   origin        [x, y] of the frame's pivot, from its top left (default: the middle of its bottom)
+//   [clips.NAME]  named animations:
+//     frames      frame numbers, counted down each strip from 0
+//     durations   seconds per frame; a single value applies to every frame
+//     mode        "loop", "ping_pong" or "once" (default "loop")
+
+// how a sheet is cut into frames, and the animations it holds
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct SheetInfo {
+    pub frames: usize,
+    pub columns: usize,
+    pub frame_size: (Option<usize>, Option<usize>),
+    pub padding: usize,
+    pub origin: Option<(i32, i32)>,
+    // clips give frames as (column, row) already
+    pub clips: BTreeMap<String, Clip>,
+}
+
+// a sidecar file as written
+#[derive(Deserialize)]
+struct SheetFile {
+    frames: usize,
+    #[serde(default = "default_columns")]
+    columns: usize,
+    frame_width: Option<usize>,
+    frame_height: Option<usize>,
+    #[serde(default)]
+    padding: usize,
+    origin: Option<[i32; 2]>,
+    #[serde(default)]
+    clips: BTreeMap<String, ClipEntry>,
+}
+
+fn default_columns() -> usize {
+    1
+}
+
+#[derive(Deserialize)]
+struct ClipEntry {
+    frames: Vec<usize>,
+    durations: Vec<f64>,
+    #[serde(default = "default_mode")]
+    mode: String,
+}
+
+fn default_mode() -> String {
+    "loop".to_string()
+}
+
+impl SheetInfo {
+    // the sidecar file of the sheet at `sheet_path`
+    pub fn sidecar_path(sheet_path: &str) -> PathBuf {
+        Path::new(sheet_path).with_extension("toml")
+    }
+    // the metadata next to the sheet at `sheet_path`; None if it has none
+    pub fn load_for(sheet_path: &str) -> Result<Option<SheetInfo>, String> {
+        let path = SheetInfo::sidecar_path(sheet_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        SheetInfo::parse(&text).map(Some).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+    pub fn parse(text: &str) -> Result<SheetInfo, String> {
+        let file: SheetFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        if file.frames == 0 || file.columns == 0 {
+            return Err("A sheet needs at least one frame and one column".to_string());
+        }
+        let mut info = SheetInfo {
+            frames: file.frames,
+            columns: file.columns,
+            frame_size: (file.frame_width, file.frame_height),
+            padding: file.padding,
+            origin: file.origin.map(|[x, y]| (x, y)),
+            clips: BTreeMap::new(),
+        };
+        for (name, entry) in &file.clips {
+            let mode = match entry.mode.as_str() {
+                "loop" => PlayMode::Loop,
+                "ping_pong" => PlayMode::PingPong,
+                "once" => PlayMode::Once,
+                other => return Err(format!("Clip {} has an unknown mode {}", name, other)),
+            };
+            if entry.frames.is_empty() {
+                return Err(format!("Clip {} has no frames", name));
+            }
+            if entry.durations.len() != 1 && entry.durations.len() != entry.frames.len() {
+                return Err(format!("Clip {} needs one duration, or one per frame", name));
+            }
+            let mut clip = Clip::new(mode);
+            for (i, &frame) in entry.frames.iter().enumerate() {
+                let cell = info.cell(frame).ok_or_else(|| {
+                    format!("Clip {} uses frame {}, but the sheet has {}", name, frame, info.frames)
+                })?;
+                clip.add_frame(cell, entry.durations[i.min(entry.durations.len() - 1)]);
+            }
+            info.clips.insert(name.clone(), clip);
+        }
+        Ok(info)
+    }
+    // frames in each strip
+    pub fn rows(&self) -> usize {
+        self.frames.div_ceil(self.columns)
+    }
+    // the (column, row) of frame number `frame`
+    pub fn cell(&self, frame: usize) -> Option<(usize, usize)> {
+        if frame >= self.frames {
+            return None;
+        }
+        Some((frame / self.rows(), frame % self.rows()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sheets_are_read_with_defaults() {
+        let info = SheetInfo::parse("frames = 3").unwrap();
+        assert_eq!((info.frames, info.columns, info.padding), (3, 1, 0));
+        assert_eq!(info.frame_size, (None, None));
+        assert_eq!(info.origin, None);
+        assert!(info.clips.is_empty());
+    }
+
+    #[test]
+    fn clip_frames_become_cells_down_each_strip() {
+        let info = SheetInfo::parse(r#"
+            frames = 5
+            columns = 2
+            frame_width = 16
+            frame_height = 24
+            padding = 2
+            origin = [8, 20]
+
+            [clips.walk]
+            frames = [0, 2, 3, 4]
+            durations = [0.1]
+            mode = "ping_pong"
+
+            [clips.die]
+            frames = [1, 4]
+            durations = [0.2, 0.5]
+            mode = "once"
+        "#).unwrap();
+        assert_eq!(info.rows(), 3);
+        assert_eq!((info.frame_size, info.padding, info.origin), ((Some(16), Some(24)), 2, Some((8, 20))));
+        assert_eq!(info.cell(4), Some((1, 1)));
+        assert_eq!(info.cell(5), None);
+        assert_eq!(info.clips["walk"].frames(), &[(0, 0), (0, 2), (1, 0), (1, 1)]);
+        assert_eq!(info.clips["die"].frames(), &[(0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn bad_sheets_are_rejected() {
+        let bad = [
+            "frames = 0",
+            "frames = 2\ncolumns = 0",
+            "frames = 2\n[clips.a]\nframes = [0]\ndurations = [1.0]\nmode = \"bounce\"",
+            "frames = 2\n[clips.a]\nframes = []\ndurations = [1.0]",
+            "frames = 2\n[clips.a]\nframes = [0, 2]\ndurations = [1.0]",
+            "frames = 2\n[clips.a]\nframes = [0, 1]\ndurations = [1.0, 1.0, 1.0]",
+            "columns = 2",
+        ];
+        for text in bad.iter() {
+            assert!(SheetInfo::parse(text).is_err(), "{}", text);
+        }
+    }
+}
@@ -1,48 +1,96 @@
-use sdl2::video::WindowContext;
-use sdl2::render::{TextureCreator, Canvas, WindowCanvas};
-use enum_map::EnumMap;
-use crate::sprite::Sprite;
-use strum::IntoEnumIterator;
+use std::collections::HashMap;
+use sdl2::render::{Canvas, RenderTarget};
+use crate::assets::{Assets, SpriteHandle};
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
-use enum_map::Enum;
 use crate::common::Size;
-use sdl2::event::EventType::TextInput;
+use crate::autotile;
+use crate::tile_registry::{TileInfo, TILE_REGISTRY};
 
+// numeric tile id; what each id means comes from the tile registry
 #[derive(Clone)]
 #[derive(Copy)]
-#[derive(Display)]
 #[derive(Debug)]
-#[derive(Enum)]
-#[derive(EnumIter)]
 #[derive(PartialEq)]
-pub enum TileId {
-    Nothing,
-    Dirt,
-    Stone,
-    Grass,
+#[derive(Eq)]
+#[derive(Hash)]
+pub struct TileId(pub u16);
+
+impl TileId {
+    // empty space; always present, never drawn
+    pub const NOTHING: TileId = TileId(0);
+    pub fn info(self) -> &'static TileInfo {
+        TILE_REGISTRY.get(self)
+    }
+}
+
+impl From<TileId> for u16 {
+    fn from(id: TileId) -> u16 {
+        id.0
+    }
+}
+
+// the part of its cell a tile fills; only matters for solid tiles
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+pub enum TileShape {
+    Full,
+    // the bottom half of the cell
+    Half,
+    // floor slopes, named after the side they are highest at
+    SlopeUpLeft,
+    SlopeUpRight,
+}
+
+impl TileShape {
+    pub const ALL: [TileShape; 4] = [TileShape::Full, TileShape::Half, TileShape::SlopeUpLeft, TileShape::SlopeUpRight];
+    // inverse of `shape as u8`, used when reading tiles back from disk
+    pub fn from_u8(n: u8) -> Option<TileShape> {
+        TileShape::ALL.get(usize::from(n)).cloned()
+    }
+    // how high the tile reaches at `fx` across the cell (0 is the left edge, 1 the right), as a
+    // fraction of the cell; the bottom of every shape is flat
+    pub fn height_at(self, fx: f64) -> f64 {
+        match self {
+            TileShape::Full => 1.0,
+            TileShape::Half => 0.5,
+            TileShape::SlopeUpLeft => 1.0 - fx,
+            TileShape::SlopeUpRight => fx,
+        }
+    }
 }
 
-pub const TILE_SIZE: u32 = 8;
+// size of a tile on screen at a zoom of 1, in pixels
+pub const TILE_SIZE: u32 = 16;
 lazy_static! {
-    pub static ref TILE_QUAD: Rect = Rect::new(0, 0, TILE_SIZE as u32, TILE_SIZE as u32);
+    pub static ref TILE_QUAD: Rect = Rect::new(0, 0, TILE_SIZE, TILE_SIZE);
 }
+#[derive(Clone)]
+#[derive(Copy)]
 #[derive(Debug)]
 pub struct Tile {
     id: TileId,
-    neighbors: [Option<TileId>; 4],
+    // which of the autotile variants is drawn
     frame: u32,
     solid: bool,
+    shape: TileShape,
+    // where the cell is in the furniture it is part of, counted right and up from the furniture's
+    // bottom left cell; (0, 0) for everything else
+    part: (u8, u8),
     neighbor_bf: u32,
 }
 
 impl Tile {
-    pub fn new() -> Tile {
+    pub const fn new() -> Tile {
         Tile {
-            id: TileId::Nothing,
-            neighbors: [None; 4],
+            id: TileId::NOTHING,
             frame: 0,
             solid: false,
+            shape: TileShape::Full,
+            part: (0, 0),
             neighbor_bf: 0,
         }
     }
@@ -55,12 +103,20 @@ impl Tile {
     pub fn set_solid(&mut self, solid: bool) {
         self.solid = solid;
     }
-    pub fn set_neighbors(&mut self, neighbors: [Option<TileId>; 4]) {
+    pub fn set_shape(&mut self, shape: TileShape) {
+        self.shape = shape;
+    }
+    pub fn set_part(&mut self, part: (u8, u8)) {
+        self.part = part;
+    }
+    // neighbors are in the order of the bits in `autotile`: left, down, right, up, up-left,
+    // up-right, down-left, down-right; `None` (outside the world) always connects
+    pub fn set_neighbors(&mut self, neighbors: [Option<TileId>; 8]) {
         self.neighbor_bf = 0;
-        for i in 0u8..4 {
+        for i in 0u8..8 {
             let neighbor_counts: bool;
             if let Some(n) = neighbors[usize::from(i)] {
-                neighbor_counts = n != TileId::Nothing;
+                neighbor_counts = autotile::merges(self.id, n);
             } else {
                 neighbor_counts = true;
             }
@@ -68,7 +124,6 @@ impl Tile {
                 self.neighbor_bf += 2u32.pow(u32::from(i));
             }
         }
-        self.neighbors = neighbors;
     }
     pub fn get_id(&self) -> TileId {
         self.id
@@ -76,31 +131,76 @@ impl Tile {
     pub fn get_frame(&self) -> u32 {
         self.frame
     }
+    pub fn get_solid(&self) -> bool {
+        self.solid
+    }
+    pub fn get_shape(&self) -> TileShape {
+        self.shape
+    }
+    pub fn get_part(&self) -> (u8, u8) {
+        self.part
+    }
+    #[allow(dead_code)]
     pub fn get_neighbor_bf(&self) -> u32 {
         self.neighbor_bf
     }
-    pub fn render(&self, atlases: &EnumMap<TileId, Sprite>, canvas: &mut WindowCanvas, dest: Rect) -> Result<(), String> {
-        let tile_sprite = &atlases[self.id];
-        let clip = Rect::new((TILE_SIZE * self.frame) as i32, (TILE_SIZE * self.neighbor_bf) as i32, TILE_SIZE, TILE_SIZE);
-        tile_sprite.render(canvas, dest.x(), dest.y(), Some(Size{w: dest.width(), h: dest.height()}), Some(clip))
+    pub fn render<T: RenderTarget>(&self, atlases: &TileAtlases, canvas: &mut Canvas<T>, dest: Rect) -> Result<(), String> {
+        let tile_sprite = match atlases.get(&self.id) {
+            Some(s) => s.get(),
+            None => return Ok(()),
+        };
+        let info = self.id.info();
+        if let Some((_, h)) = info.object {
+            // every cell of the furniture draws its own frame, counting rows down from the top
+            let step = (info.frame_size + info.frame_padding) as i32;
+            let (cx, cy) = (i32::from(self.part.0), h as i32 - 1 - i32::from(self.part.1));
+            let clip = Rect::new(cx * step, cy * step, info.frame_size, info.frame_size);
+            return tile_sprite.render(canvas, dest.x(), dest.y(), Some(Size{w: dest.width(), h: dest.height()}), Some(clip));
+        }
+        let clip = autotile::frame_rect(info.layout, info.frame_size, info.frame_padding, self.neighbor_bf, self.frame);
+        match self.shape {
+            TileShape::Full => {
+                tile_sprite.render(canvas, dest.x(), dest.y(), Some(Size{w: dest.width(), h: dest.height()}), Some(clip))
+            }
+            // the top half of the frame, so the half block keeps the frame's edge on top
+            TileShape::Half => {
+                let h = dest.height() - dest.height() / 2;
+                let clip = Rect::new(clip.x(), clip.y(), clip.width(), clip.height() / 2);
+                tile_sprite.render(canvas, dest.x(), dest.bottom() - h as i32, Some(Size{w: dest.width(), h}), Some(clip))
+            }
+            // one column of the frame at a time, each cut to the slope's height there
+            TileShape::SlopeUpLeft | TileShape::SlopeUpRight => {
+                let columns = clip.width();
+                for c in 0..columns {
+                    let fraction = self.shape.height_at((f64::from(c) + 0.5) / f64::from(columns));
+                    let clip_h = ((f64::from(clip.height()) * fraction).round() as u32).max(1);
+                    let h = ((f64::from(dest.height()) * fraction).round() as u32).max(1);
+                    let left = dest.x() + (dest.width() * c / columns) as i32;
+                    let right = dest.x() + (dest.width() * (c + 1) / columns) as i32;
+                    if right > left {
+                        let column_clip = Rect::new(clip.x() + c as i32, clip.y(), 1, clip_h);
+                        tile_sprite.render(canvas, left, dest.bottom() - h as i32,
+                                           Some(Size{w: (right - left) as u32, h}), Some(column_clip))?;
+                    }
+                }
+                Ok(())
+            }
+        }
     }
 }
 
-pub fn load_tile_atlases<'tc>(texture_creator: &'tc TextureCreator<WindowContext>)
-                              -> Result<EnumMap<TileId, Sprite<'tc>>, String> {
+// one sheet per registered tile, keyed by id
+pub type TileAtlases<'a> = HashMap<TileId, SpriteHandle<'a>>;
+
+pub fn load_tile_atlases<'tc, C>(assets: &mut Assets<'tc, C>) -> Result<TileAtlases<'tc>, String> {
     let magenta = Color { r: 255, g: 0, b: 255, a: 255 };
-    let mut tile_atlases = EnumMap::<TileId, Sprite<'tc>>::new();
-    for id in TileId::iter() {
-        if id == TileId::Nothing {
-            continue;
-        }
-        tile_atlases[id] = Sprite::new();
-        if let Err(msg) = tile_atlases[id].load_from_file(&texture_creator,
-                                                          &format!("sprites/Tile_{}.png", id.to_string()),
-                                                          Some(magenta),
-        ) {
-            println!("{}", msg);
-        }
+    let mut tile_atlases = TileAtlases::new();
+    for info in TILE_REGISTRY.iter() {
+        let texture = match &info.texture {
+            Some(t) => t,
+            None => continue,
+        };
+        tile_atlases.insert(info.id, assets.sprite(texture, Some(magenta)));
     }
     Ok(tile_atlases)
 }
@@ -0,0 +1,160 @@
+use serde::Deserialize;
+use crate::autotile::SheetLayout;
+use crate::registry::{self, Entry, Registry};
+use crate::tile::TileId;
+
+pub const TILE_REGISTRY_FILE: &str = "data/tiles.toml";
+
+lazy_static! {
+    // every tile the game knows about, read once from `TILE_REGISTRY_FILE`
+    pub static ref TILE_REGISTRY: TileRegistry = TileRegistry::load_from_file(TILE_REGISTRY_FILE)
+        .unwrap_or_else(|msg| panic!("ERROR: Could not load the tile registry: {}", msg));
+}
+
+// everything about a kind of tile that is not stored per tile
+#[derive(Debug)]
+pub struct TileInfo {
+    pub id: TileId,
+    pub name: String,
+    // `None` for tiles that are never drawn
+    pub texture: Option<String>,
+    pub layout: SheetLayout,
+    pub frame_size: u32,
+    pub frame_padding: u32,
+    pub solid: bool,
+    // other tiles this one joins up with when autotiling; always symmetric
+    pub merge: Vec<TileId>,
+    pub hardness: u32,
+    // has to rest on full solid tiles, all along its width; breaks along with any of them
+    pub needs_support: bool,
+    // furniture `(width, height)` cells large, rising up and to the right of the cell it was placed
+    // in; every cell it covers holds it, and the sheet has one frame per cell. `None` for autotiled
+    // blocks
+    pub object: Option<(u32, u32)>,
+    // RGB light given off by the tile
+    pub light: (u8, u8, u8),
+}
+
+// a `[[tile]]` table as written in the registry file
+#[derive(Deserialize)]
+struct TileEntry {
+    id: u16,
+    name: String,
+    texture: String,
+    #[serde(default = "default_layout")]
+    layout: String,
+    #[serde(default = "default_frame_size")]
+    frame_size: u32,
+    #[serde(default = "default_frame_padding")]
+    frame_padding: u32,
+    #[serde(default = "default_solid")]
+    solid: bool,
+    #[serde(default)]
+    merge: Vec<String>,
+    #[serde(default = "default_hardness")]
+    hardness: u32,
+    #[serde(default)]
+    needs_support: bool,
+    object: Option<[u32; 2]>,
+    #[serde(default)]
+    light: [u8; 3],
+}
+
+fn default_layout() -> String {
+    "terraria".to_string()
+}
+fn default_frame_size() -> u32 {
+    16
+}
+fn default_frame_padding() -> u32 {
+    2
+}
+fn default_solid() -> bool {
+    true
+}
+fn default_hardness() -> u32 {
+    50
+}
+
+#[derive(Deserialize)]
+struct RegistryFile {
+    #[serde(default)]
+    tile: Vec<TileEntry>,
+}
+
+pub type TileRegistry = Registry<TileInfo>;
+
+impl Entry for TileInfo {
+    type Id = TileId;
+    const KIND: &'static str = "Tile";
+    fn id(&self) -> TileId {
+        self.id
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Registry<TileInfo> {
+    pub fn load_from_file(path: &str) -> Result<TileRegistry, String> {
+        registry::load_file(path, TileRegistry::parse)
+    }
+    pub fn parse(text: &str) -> Result<TileRegistry, String> {
+        let file: RegistryFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        let mut registry = TileRegistry::default();
+        registry.insert(TileInfo {
+            id: TileId::NOTHING,
+            name: "nothing".to_string(),
+            texture: None,
+            layout: SheetLayout::Terraria,
+            frame_size: default_frame_size(),
+            frame_padding: default_frame_padding(),
+            solid: false,
+            merge: Vec::new(),
+            hardness: 0,
+            needs_support: false,
+            object: None,
+            light: (0, 0, 0),
+        })?;
+        for entry in &file.tile {
+            let layout = SheetLayout::from_name(&entry.layout)
+                .ok_or_else(|| format!("Tile {} has an unknown layout {}", entry.name, entry.layout))?;
+            if let Some([w, h]) = entry.object {
+                if w == 0 || h == 0 || w > u32::from(u8::MAX) || h > u32::from(u8::MAX) {
+                    return Err(format!("Tile {} needs an object size between 1 and {}", entry.name, u8::MAX));
+                }
+            }
+            registry.insert(TileInfo {
+                id: TileId(entry.id),
+                name: entry.name.clone(),
+                texture: Some(entry.texture.clone()),
+                layout,
+                frame_size: entry.frame_size,
+                frame_padding: entry.frame_padding,
+                solid: entry.solid,
+                merge: Vec::new(),
+                hardness: entry.hardness,
+                needs_support: entry.needs_support,
+                object: entry.object.map(|[w, h]| (w, h)),
+                light: (entry.light[0], entry.light[1], entry.light[2]),
+            })?;
+        }
+        // merge sets can only be resolved once every name is known
+        for entry in &file.tile {
+            for other in &entry.merge {
+                let other_id = registry.id(other)
+                    .ok_or_else(|| format!("Tile {} merges with unknown tile {}", entry.name, other))?;
+                registry.add_merge(TileId(entry.id), other_id);
+                registry.add_merge(other_id, TileId(entry.id));
+            }
+        }
+        Ok(registry)
+    }
+    fn add_merge(&mut self, id: TileId, other: TileId) {
+        if let Some(info) = self.get_mut(id) {
+            if !info.merge.contains(&other) {
+                info.merge.push(other);
+            }
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use sdl2::event::Event;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{BlendMode, Canvas, RenderTarget};
+use crate::common::Size;
+use crate::font::{Align, Font, TextStyle};
+use crate::item::{ItemAtlases, ItemStack};
+
+pub const TEXT_COLOR: Color = Color { r: 255, g: 255, b: 255, a: 255 };
+const PANEL_COLOR: Color = Color { r: 24, g: 28, b: 70, a: 200 };
+const BORDER_COLOR: Color = Color { r: 10, g: 10, b: 30, a: 255 };
+const BUTTON_COLOR: Color = Color { r: 50, g: 60, b: 130, a: 220 };
+const HOVER_COLOR: Color = Color { r: 80, g: 95, b: 180, a: 230 };
+const SLOT_COLOR: Color = Color { r: 40, g: 50, b: 110, a: 170 };
+const HIGHLIGHT_COLOR: Color = Color { r: 230, g: 200, b: 60, a: 255 };
+const BAR_BACK_COLOR: Color = Color { r: 20, g: 10, b: 10, a: 200 };
+// in screen pixels
+const TEXT_SCALE: u32 = 2;
+const PADDING: i32 = 4;
+const TOOLTIP_OFFSET: i32 = 16;
+// longer tooltips wrap onto more lines
+const TOOLTIP_WIDTH: u32 = 320;
+// icons bigger than this fraction of their slot are shrunk to fit
+const ICON_FILL: f64 = 0.75;
+
+// how a grid of item slots is laid out, in screen pixels
+pub struct SlotGrid {
+    pub origin: (i32, i32),
+    pub columns: usize,
+    pub slot_size: u32,
+    pub gap: i32,
+}
+
+impl SlotGrid {
+    pub fn slot_rect(&self, i: usize) -> Rect {
+        let step = self.slot_size as i32 + self.gap;
+        let x = self.origin.0 + (i % self.columns) as i32 * step;
+        let y = self.origin.1 + (i / self.columns) as i32 * step;
+        Rect::new(x, y, self.slot_size, self.slot_size)
+    }
+    // the height of `slots` slots, gaps included
+    pub fn height(&self, slots: usize) -> u32 {
+        let rows = slots.div_ceil(self.columns) as u32;
+        (rows * (self.slot_size + self.gap as u32)).saturating_sub(self.gap as u32)
+    }
+}
+
+// an immediate-mode ui: each widget is drawn and checked for clicks in the same call, every
+// frame, so whoever shows a widget also handles what it does; the mouse is tracked from the
+// same events the rest of the game gets
+pub struct Ui<'a> {
+    font: Font<'a>,
+    mouse: (i32, i32),
+    // left and right buttons that went down since the last frame
+    pressed: [bool; 2],
+    // wheel clicks since the last frame, positive away from the user
+    wheel: i32,
+    // everything drawn this frame and the last; the mouse is over the ui when it is over one of
+    // last frame's areas, since this frame's are not known until it is drawn
+    areas: Vec<Rect>,
+    last_areas: Vec<Rect>,
+    tooltip: Option<String>,
+    blend_mode: BlendMode,
+}
+
+impl<'a> Ui<'a> {
+    pub fn new(font: Font<'a>) -> Ui<'a> {
+        let mut font = font;
+        font.set_scale(TEXT_SCALE);
+        Ui {
+            font,
+            mouse: (0, 0),
+            pressed: [false; 2],
+            wheel: 0,
+            areas: Vec::new(),
+            last_areas: Vec::new(),
+            tooltip: None,
+            blend_mode: BlendMode::None,
+        }
+    }
+    // returns true when the event was meant for the ui, so the world should not act on it too
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        match *event {
+            Event::MouseMotion { x, y, .. } => {
+                self.mouse = (x, y);
+                false
+            }
+            Event::MouseButtonDown { mouse_btn, x, y, .. } => {
+                self.mouse = (x, y);
+                match button_index(mouse_btn) {
+                    Some(i) if self.wants_mouse() => {
+                        self.pressed[i] = true;
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            Event::MouseWheel { y, .. } if self.wants_mouse() => {
+                self.wheel += y;
+                true
+            }
+            _ => false,
+        }
+    }
+    // whether the mouse is over something the ui drew
+    pub fn wants_mouse(&self) -> bool {
+        self.last_areas.iter().any(|r| r.contains_point(self.mouse))
+    }
+    pub fn get_mouse(&self) -> (i32, i32) {
+        self.mouse
+    }
+    pub fn hovered(&self, rect: Rect) -> bool {
+        rect.contains_point(self.mouse)
+    }
+    // whether `button` went down over `rect` this frame
+    pub fn clicked(&self, rect: Rect, button: MouseButton) -> bool {
+        button_index(button).is_some_and(|i| self.pressed[i]) && self.hovered(rect)
+    }
+    // wheel clicks over `rect` this frame
+    pub fn scrolled(&self, rect: Rect) -> i32 {
+        if self.hovered(rect) { self.wheel } else { 0 }
+    }
+    // call before drawing any widget
+    pub fn begin_frame<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) {
+        self.blend_mode = canvas.blend_mode();
+        canvas.set_blend_mode(BlendMode::Blend);
+    }
+    // draws the tooltip over everything else and forgets this frame's clicks
+    pub fn end_frame<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) -> Result<(), String> {
+        if let Some(text) = self.tooltip.take() {
+            let size = self.font.measure_wrapped(&text, Some(TOOLTIP_WIDTH));
+            let (w, h) = (size.w as i32 + 2 * PADDING, size.h as i32 + 2 * PADDING);
+            let (width, height) = canvas.output_size()?;
+            // kept on screen near the right and bottom edges
+            let x = (self.mouse.0 + TOOLTIP_OFFSET).min(width as i32 - w).max(0);
+            let y = (self.mouse.1 + TOOLTIP_OFFSET).min(height as i32 - h).max(0);
+            self.fill(canvas, Rect::new(x, y, w as u32, h as u32), PANEL_COLOR)?;
+            let style = TextStyle { wrap: Some(TOOLTIP_WIDTH), ..TextStyle::new(TEXT_COLOR) };
+            self.font.render_styled(canvas, x + PADDING, y + PADDING, &text, &style)?;
+        }
+        self.pressed = [false; 2];
+        self.wheel = 0;
+        self.last_areas = std::mem::take(&mut self.areas);
+        canvas.set_blend_mode(self.blend_mode);
+        Ok(())
+    }
+    // a box that blocks the mouse from the world, for other widgets to sit on
+    pub fn panel<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, rect: Rect) -> Result<(), String> {
+        self.fill(canvas, rect, PANEL_COLOR)?;
+        canvas.set_draw_color(BORDER_COLOR);
+        canvas.draw_rect(rect)
+    }
+    pub fn label<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, x: i32, y: i32, text: &str,
+                                  color: Color) -> Result<(), String> {
+        self.font.render(canvas, x, y, text, color)
+    }
+    // `text` wrapped to the width of `rect` and aligned within it
+    pub fn text<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, rect: Rect, text: &str, color: Color,
+                                 align: Align) -> Result<(), String> {
+        let style = TextStyle { color, align, wrap: Some(rect.width()) };
+        self.font.render_styled(canvas, rect.x(), rect.y(), text, &style)
+    }
+    pub fn measure(&mut self, text: &str) -> Size {
+        self.font.measure(text)
+    }
+    // returns true on the frame it is clicked
+    pub fn button<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, rect: Rect, text: &str)
+                                   -> Result<bool, String> {
+        let color = if self.hovered(rect) { HOVER_COLOR } else { BUTTON_COLOR };
+        self.fill(canvas, rect, color)?;
+        canvas.set_draw_color(BORDER_COLOR);
+        canvas.draw_rect(rect)?;
+        let size = self.font.measure(text);
+        let x = rect.center().x() - size.w as i32 / 2;
+        let y = rect.center().y() - size.h as i32 / 2;
+        self.font.render(canvas, x, y, text, TEXT_COLOR)?;
+        Ok(self.clicked(rect, MouseButton::Left))
+    }
+    // a meter filled from the left by `fraction`, like a health bar
+    pub fn bar<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, rect: Rect, fraction: f64,
+                                color: Color) -> Result<(), String> {
+        self.fill(canvas, rect, BAR_BACK_COLOR)?;
+        let w = (f64::from(rect.width()) * fraction.clamp(0.0, 1.0)).round() as u32;
+        if w > 0 {
+            canvas.set_draw_color(color);
+            canvas.fill_rect(Rect::new(rect.x(), rect.y(), w, rect.height()))?;
+        }
+        canvas.set_draw_color(BORDER_COLOR);
+        canvas.draw_rect(rect)
+    }
+    // an item slot showing `stack` with its count; hovering shows the item's name; returns the
+    // button it was clicked with
+    pub fn slot<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, rect: Rect, stack: Option<&ItemStack>,
+                                 highlighted: bool, atlases: &ItemAtlases) -> Result<Option<MouseButton>, String> {
+        self.fill(canvas, rect, SLOT_COLOR)?;
+        if highlighted {
+            canvas.set_draw_color(HIGHLIGHT_COLOR);
+            canvas.draw_rect(rect)?;
+            canvas.draw_rect(Rect::new(rect.x() + 1, rect.y() + 1, rect.width() - 2, rect.height() - 2))?;
+        }
+        if let Some(stack) = stack {
+            let icon_size = (f64::from(rect.width().min(rect.height())) * ICON_FILL) as u32;
+            self.item_icon(canvas, rect.center(), icon_size, stack, atlases)?;
+            if self.hovered(rect) {
+                self.tooltip(&display_name(&stack.get_id().info().name));
+            }
+        }
+        let clicked = [MouseButton::Left, MouseButton::Right].iter().copied().find(|&b| self.clicked(rect, b));
+        Ok(clicked)
+    }
+    // a slot for each stack in `slots`, laid out by `grid`; returns the index into `slots` of the one
+    // clicked and the button it was clicked with
+    pub fn slot_grid<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, grid: &SlotGrid, slots: &[Option<&ItemStack>],
+                                      highlighted: Option<usize>, atlases: &ItemAtlases)
+                                      -> Result<Option<(usize, MouseButton)>, String> {
+        let mut clicked = None;
+        for (i, stack) in slots.iter().enumerate() {
+            if let Some(button) = self.slot(canvas, grid.slot_rect(i), *stack, highlighted == Some(i), atlases)? {
+                clicked = Some((i, button));
+            }
+        }
+        Ok(clicked)
+    }
+    // draws `stack`'s sprite centered on `center`, no bigger than `max_size`, with its count in the
+    // bottom right when there is more than one
+    pub fn item_icon<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, center: Point, max_size: u32,
+                                      stack: &ItemStack, atlases: &ItemAtlases) -> Result<(), String> {
+        if let Some(sprite) = atlases.get(&stack.get_id()) {
+            let sprite = sprite.get();
+            let scale = (f64::from(max_size) / f64::from(sprite.width().max(sprite.height()).max(1))).min(1.0);
+            let size = Size {
+                w: (f64::from(sprite.width()) * scale).round() as u32,
+                h: (f64::from(sprite.height()) * scale).round() as u32,
+            };
+            sprite.render(canvas, center.x() - size.w as i32 / 2, center.y() - size.h as i32 / 2, Some(size), None)?;
+        }
+        if stack.get_count() > 1 {
+            let count = stack.get_count().to_string();
+            let size = self.font.measure(&count);
+            let x = center.x() + max_size as i32 / 2 - size.w as i32 + PADDING;
+            let y = center.y() + max_size as i32 / 2 - size.h as i32 + PADDING;
+            self.font.render(canvas, x, y, &count, TEXT_COLOR)?;
+        }
+        Ok(())
+    }
+    // shows `text` next to the mouse once everything else is drawn; the last call in a frame wins
+    pub fn tooltip(&mut self, text: &str) {
+        self.tooltip = Some(text.to_string());
+    }
+    // fills `rect` and marks it as part of the ui
+    fn fill<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, rect: Rect, color: Color) -> Result<(), String> {
+        self.areas.push(rect);
+        canvas.set_draw_color(color);
+        canvas.fill_rect(rect)
+    }
+}
+
+fn button_index(button: MouseButton) -> Option<usize> {
+    match button {
+        MouseButton::Left => Some(0),
+        MouseButton::Right => Some(1),
+        _ => None,
+    }
+}
+
+// "iron_pickaxe" as "Iron Pickaxe"
+pub fn display_name(name: &str) -> String {
+    name.split('_').map(|word| {
+        let mut chars = word.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        }
+    }).collect::<Vec<String>>().join(" ")
+}
@@ -1,13 +1,16 @@
 use crate::common::{Position, Size};
-use sdl2::render::WindowCanvas;
+use sdl2::render::{Canvas, RenderTarget};
 use crate::world::World;
 use sdl2::rect::Rect;
-use sdl2::video::WindowPos::Positioned;
-use crate::tile::{Tile, TILE_SIZE};
-use sdl2::event::EventType::TextInput;
+use crate::tile::{TileAtlases, TileId, TILE_SIZE};
+use crate::chunk::{Chunk, CHUNK_SIZE};
+use crate::wall::{WallAtlases, WallId};
+use crate::liquid::LiquidAtlases;
 
+#[derive(Clone)]
 pub(crate) struct Viewport {
-    pos: Position,
+    // the tile at the center of the view; fractional so that the view can move smoothly
+    pos: (f64, f64),
     output_dimensions: Size,
     zoom_factor: f64,
 }
@@ -15,13 +18,25 @@ pub(crate) struct Viewport {
 impl Viewport {
     pub fn new() -> Viewport {
         Viewport {
-            pos: Position { x: 0, y: 0 },
+            pos: (0.0, 0.0),
             output_dimensions: Size { w: 0, h: 0 },
             zoom_factor: 1.0,
         }
     }
     pub fn set_pos(&mut self, pos: Position) {
-        self.pos = pos;
+        self.pos = (f64::from(pos.x), f64::from(pos.y));
+    }
+    pub fn set_center(&mut self, x: f64, y: f64) {
+        self.pos = (x, y);
+    }
+    pub fn get_center(&self) -> (f64, f64) {
+        self.pos
+    }
+    pub fn get_zoom(&self) -> f64 {
+        self.zoom_factor
+    }
+    pub fn get_output_dimensions(&self) -> Size {
+        self.output_dimensions
     }
     pub fn set_output_dimensions(&mut self, output_dimensions: Size) {
         self.output_dimensions = output_dimensions;
@@ -29,22 +44,94 @@ impl Viewport {
     pub fn set_zoom(&mut self, zoom_factor: f64) {
         self.zoom_factor = zoom_factor;
     }
-    pub fn render(&self, canvas: &mut WindowCanvas, world: &World) -> Result<(), String> {
-       let world_bounds: Rect = Rect::new(
-           self.pos.x - ((self.output_dimensions.w / 2) as f64 / self.zoom_factor / TILE_SIZE as f64) as i32,
-           self.pos.y - ((self.output_dimensions.h / 2) as f64 / self.zoom_factor / TILE_SIZE as f64) as i32,
-           (self.output_dimensions.w as f64 / self.zoom_factor / TILE_SIZE as f64) as u32,
-           (self.output_dimensions.h as f64 / self.zoom_factor / TILE_SIZE as f64) as u32
-        );
-        for i in bounds.left()..bounds.right() {
-            for j in bounds.top()..bounds.bottom() {
-                if i < 0 || j < 0 || i > world.width() || j > world.height() {
+    // size of one tile on screen, in pixels
+    pub fn tile_pixels(&self) -> f64 {
+        TILE_SIZE as f64 * self.zoom_factor
+    }
+    // the range of tiles (in tile coordinates) that overlap the output, clamped to the world
+    pub fn visible_tiles(&self, world: &World) -> Rect {
+        let tile_pixels = self.tile_pixels();
+        let half_w = self.output_dimensions.w as f64 / 2.0 / tile_pixels;
+        let half_h = self.output_dimensions.h as f64 / 2.0 / tile_pixels;
+        let left = ((self.pos.0 - half_w).floor() as i32).max(0);
+        let top = ((self.pos.1 - half_h).floor() as i32).max(0);
+        let right = ((self.pos.0 + half_w).ceil() as i32).min(world.width() as i32);
+        let bottom = ((self.pos.1 + half_h).ceil() as i32).min(world.height() as i32);
+        Rect::new(left, top, (right - left).max(0) as u32, (bottom - top).max(0) as u32)
+    }
+    // where the tile at (x, y) ends up on screen; edges are rounded separately so that
+    // neighboring tiles never leave gaps at fractional zoom levels
+    pub fn tile_to_screen(&self, x: i32, y: i32) -> Rect {
+        let tile_pixels = self.tile_pixels();
+        let origin_x = self.output_dimensions.w as f64 / 2.0 - self.pos.0 * tile_pixels;
+        let origin_y = self.output_dimensions.h as f64 / 2.0 - self.pos.1 * tile_pixels;
+        let left = (origin_x + x as f64 * tile_pixels).round() as i32;
+        let top = (origin_y + y as f64 * tile_pixels).round() as i32;
+        let right = (origin_x + (x + 1) as f64 * tile_pixels).round() as i32;
+        let bottom = (origin_y + (y + 1) as f64 * tile_pixels).round() as i32;
+        Rect::new(left, top, (right - left).max(1) as u32, (bottom - top).max(1) as u32)
+    }
+    // where a point in tile coordinates ends up on screen
+    pub fn point_to_screen(&self, x: f64, y: f64) -> (i32, i32) {
+        let tile_pixels = self.tile_pixels();
+        ((self.output_dimensions.w as f64 / 2.0 + (x - self.pos.0) * tile_pixels).round() as i32,
+         (self.output_dimensions.h as f64 / 2.0 + (y - self.pos.1) * tile_pixels).round() as i32)
+    }
+    // the inverse of `tile_to_screen`: the point in tile coordinates under a pixel of the output
+    pub fn screen_to_tile(&self, x: i32, y: i32) -> (f64, f64) {
+        let tile_pixels = self.tile_pixels();
+        (self.pos.0 + (f64::from(x) - self.output_dimensions.w as f64 / 2.0) / tile_pixels,
+         self.pos.1 + (f64::from(y) - self.output_dimensions.h as f64 / 2.0) / tile_pixels)
+    }
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, world: &World,
+                  tile_atlases: &TileAtlases, wall_atlases: &WallAtlases,
+                  liquid_atlases: &LiquidAtlases) -> Result<(), String> {
+        let bounds = self.visible_tiles(world);
+        // walls overhang their cell by half a tile, so the ring just outside the view can still
+        // show; they all go down first so that every tile ends up in front of them
+        let wall_bounds = Rect::new(bounds.x() - 1, bounds.y() - 1, bounds.width() + 2, bounds.height() + 2);
+        for (cx, cy, chunk) in self.visible_chunks(world, wall_bounds) {
+            let base_x = (cx * CHUNK_SIZE) as i32;
+            let base_y = (cy * CHUNK_SIZE) as i32;
+            for (lx, ly, w) in chunk.iter_walls() {
+                let (i, j) = (base_x + lx as i32, base_y + ly as i32);
+                if w.get_id() == WallId::NOTHING || !wall_bounds.contains_point((i, j)) {
                     continue;
                 }
-                let t: &Tile = world.get_tile(i as usize, j as usize).unwrap();
-                // TODO t.render(tile_atlases, canvas, Rect::new())
+                w.render(wall_atlases, canvas, self.tile_to_screen(i, j))?;
+            }
+        }
+        // liquid goes between the walls and the tiles
+        for (cx, cy, chunk) in self.visible_chunks(world, bounds) {
+            let base_x = (cx * CHUNK_SIZE) as i32;
+            let base_y = (cy * CHUNK_SIZE) as i32;
+            for (lx, ly, l) in chunk.iter_liquids() {
+                let (i, j) = (base_x + lx as i32, base_y + ly as i32);
+                if l.is_empty() || !bounds.contains_point((i, j)) {
+                    continue;
+                }
+                let covered = j > 0 && !world.get_liquid(i as usize, j as usize - 1)?.is_empty();
+                l.render(liquid_atlases, canvas, self.tile_to_screen(i, j), covered)?;
+            }
+        }
+        for (cx, cy, chunk) in self.visible_chunks(world, bounds) {
+            let base_x = (cx * CHUNK_SIZE) as i32;
+            let base_y = (cy * CHUNK_SIZE) as i32;
+            for (lx, ly, t) in chunk.iter() {
+                let (i, j) = (base_x + lx as i32, base_y + ly as i32);
+                if t.get_id() == TileId::NOTHING || !bounds.contains_point((i, j)) {
+                    continue;
+                }
+                t.render(tile_atlases, canvas, self.tile_to_screen(i, j))?;
             }
         }
         Ok(())
     }
-}
\ No newline at end of file
+    // chunks that were never allocated hold nothing to draw, so they are skipped wholesale
+    fn visible_chunks<'w>(&self, world: &'w World, bounds: Rect) -> impl Iterator<Item=(usize, usize, &'w Chunk)> {
+        let left = bounds.x().max(0) as usize;
+        let top = bounds.y().max(0) as usize;
+        world.chunks_in(left, top, (bounds.right() - left as i32).max(0) as usize,
+                        (bounds.bottom() - top as i32).max(0) as usize)
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use sdl2::render::{Canvas, RenderTarget};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use crate::assets::{Assets, SpriteHandle};
+use crate::autotile::{self, SheetLayout};
+use crate::common::Size;
+use crate::registry::{self, Entry, Registry};
+
+pub const WALL_REGISTRY_FILE: &str = "data/walls.toml";
+// walls sit behind the tiles, so they are drawn a little darker to keep them from competing
+const WALL_SHADE: Color = Color { r: 170, g: 170, b: 170, a: 255 };
+
+lazy_static! {
+    // every wall the game knows about, read once from `WALL_REGISTRY_FILE`
+    pub static ref WALL_REGISTRY: WallRegistry = WallRegistry::load_from_file(WALL_REGISTRY_FILE)
+        .unwrap_or_else(|msg| panic!("ERROR: Could not load the wall registry: {}", msg));
+}
+
+// numeric wall id; what each id means comes from the wall registry
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+#[derive(Hash)]
+pub struct WallId(pub u16);
+
+impl WallId {
+    // no wall; always present, never drawn
+    pub const NOTHING: WallId = WallId(0);
+    pub fn info(self) -> &'static WallInfo {
+        WALL_REGISTRY.get(self)
+    }
+}
+
+impl From<WallId> for u16 {
+    fn from(id: WallId) -> u16 {
+        id.0
+    }
+}
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+pub struct Wall {
+    id: WallId,
+    // which of the autotile variants is drawn
+    frame: u32,
+    neighbor_bf: u32,
+}
+
+impl Wall {
+    pub const fn new() -> Wall {
+        Wall {
+            id: WallId::NOTHING,
+            frame: 0,
+            neighbor_bf: 0,
+        }
+    }
+    pub fn set_id(&mut self, id: WallId) {
+        self.id = id;
+    }
+    pub fn set_frame(&mut self, frame: u32) {
+        self.frame = frame;
+    }
+    // same order as `Tile::set_neighbors`; walls only join up with walls of the same kind
+    pub fn set_neighbors(&mut self, neighbors: [Option<WallId>; 8]) {
+        self.neighbor_bf = 0;
+        for (i, n) in neighbors.iter().enumerate() {
+            let neighbor_counts = match n {
+                Some(n) => self.id != WallId::NOTHING && *n == self.id,
+                None => true,
+            };
+            if neighbor_counts {
+                self.neighbor_bf |= 1 << i;
+            }
+        }
+    }
+    pub fn get_id(&self) -> WallId {
+        self.id
+    }
+    pub fn get_frame(&self) -> u32 {
+        self.frame
+    }
+    // `dest` is the tile's own square; wall frames are twice the size of a tile and overhang it
+    // by half a tile on every side
+    pub fn render<T: RenderTarget>(&self, atlases: &WallAtlases, canvas: &mut Canvas<T>, dest: Rect) -> Result<(), String> {
+        let wall_sprite = match atlases.get(&self.id) {
+            Some(s) => s.get(),
+            None => return Ok(()),
+        };
+        let info = self.id.info();
+        let clip = autotile::frame_rect(SheetLayout::Terraria, info.frame_size, info.frame_padding, self.neighbor_bf, self.frame);
+        let (w, h) = (dest.width() * 2, dest.height() * 2);
+        let (x, y) = (dest.x() - (dest.width() / 2) as i32, dest.y() - (dest.height() / 2) as i32);
+        wall_sprite.render(canvas, x, y, Some(Size { w, h }), Some(clip))
+    }
+}
+
+#[derive(Debug)]
+pub struct WallInfo {
+    pub id: WallId,
+    pub name: String,
+    // sheet in the Terraria wall layout; `None` for walls that are never drawn
+    pub texture: Option<String>,
+    pub frame_size: u32,
+    pub frame_padding: u32,
+}
+
+// a `[[wall]]` table as written in the registry file
+#[derive(Deserialize)]
+struct WallEntry {
+    id: u16,
+    name: String,
+    texture: String,
+    #[serde(default = "default_frame_size")]
+    frame_size: u32,
+    #[serde(default = "default_frame_padding")]
+    frame_padding: u32,
+}
+
+fn default_frame_size() -> u32 {
+    32
+}
+fn default_frame_padding() -> u32 {
+    4
+}
+
+#[derive(Deserialize)]
+struct RegistryFile {
+    #[serde(default)]
+    wall: Vec<WallEntry>,
+}
+
+pub type WallRegistry = Registry<WallInfo>;
+
+impl Entry for WallInfo {
+    type Id = WallId;
+    const KIND: &'static str = "Wall";
+    fn id(&self) -> WallId {
+        self.id
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Registry<WallInfo> {
+    pub fn load_from_file(path: &str) -> Result<WallRegistry, String> {
+        registry::load_file(path, WallRegistry::parse)
+    }
+    pub fn parse(text: &str) -> Result<WallRegistry, String> {
+        let file: RegistryFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        let mut registry = WallRegistry::default();
+        registry.insert(WallInfo {
+            id: WallId::NOTHING,
+            name: "nothing".to_string(),
+            texture: None,
+            frame_size: default_frame_size(),
+            frame_padding: default_frame_padding(),
+        })?;
+        for entry in file.wall {
+            registry.insert(WallInfo {
+                id: WallId(entry.id),
+                name: entry.name,
+                texture: Some(entry.texture),
+                frame_size: entry.frame_size,
+                frame_padding: entry.frame_padding,
+            })?;
+        }
+        Ok(registry)
+    }
+}
+
+// one sheet per registered wall, keyed by id
+pub type WallAtlases<'a> = HashMap<WallId, SpriteHandle<'a>>;
+
+pub fn load_wall_atlases<'tc, C>(assets: &mut Assets<'tc, C>) -> Result<WallAtlases<'tc>, String> {
+    let magenta = Color { r: 255, g: 0, b: 255, a: 255 };
+    let mut wall_atlases = WallAtlases::new();
+    for info in WALL_REGISTRY.iter() {
+        let texture = match &info.texture {
+            Some(t) => t,
+            None => continue,
+        };
+        let sprite = assets.sprite(texture, Some(magenta));
+        sprite.get_mut().set_color_mod(WALL_SHADE);
+        wall_atlases.insert(info.id, sprite);
+    }
+    Ok(wall_atlases)
+}
@@ -1,25 +1,42 @@
-use crate::tile::{Tile, TileId};
+use crate::autotile;
+use std::collections::BTreeSet;
+use crate::chunk::{Chunk, CHUNK_SIZE};
+use crate::liquid::Liquid;
+use crate::tile::{Tile, TileId, TileShape};
+use crate::wall::{Wall, WallId};
+
+// handed out for cells in chunks that have never been written to
+static EMPTY_TILE: Tile = Tile::new();
+static EMPTY_WALL: Wall = Wall::new();
+static EMPTY_LIQUID: Liquid = Liquid::new();
 
 pub(crate) struct World {
     width: usize,
     height: usize,
-    tiles: Vec<Vec<Tile>>,
+    chunks_wide: usize,
+    chunks_high: usize,
+    // row-major; `None` means the whole chunk is `TileId::NOTHING` with no walls or liquid
+    chunks: Vec<Option<Chunk>>,
+    // indices of chunks whose liquid may still move; woken by any edit nearby
+    active_liquid_chunks: BTreeSet<usize>,
+    // cells edited since the lighting last looked, in the order they were edited
+    light_changes: Vec<(usize, usize)>,
 }
 
 impl World {
     pub fn new(width: usize, height: usize) -> World {
-        let mut tiles = Vec::with_capacity(width);
-        for _ in 0..width {
-            let mut col = Vec::with_capacity(height);
-            for _ in 0..height {
-                col.push(Tile::new());
-            }
-            tiles.push(col);
-        }
+        let chunks_wide = width.div_ceil(CHUNK_SIZE);
+        let chunks_high = height.div_ceil(CHUNK_SIZE);
+        let mut chunks = Vec::with_capacity(chunks_wide * chunks_high);
+        chunks.resize_with(chunks_wide * chunks_high, || None);
         World {
             width,
             height,
-            tiles,
+            chunks_wide,
+            chunks_high,
+            chunks,
+            active_liquid_chunks: BTreeSet::new(),
+            light_changes: Vec::new(),
         }
     }
     pub fn width(&self) -> usize {
@@ -32,52 +49,246 @@ impl World {
         if x >= self.width || y >= self.height {
             Err(format!("The tile at ({}, {}) is out of bounds", x, y))
         } else {
-            Ok(&self.tiles[x][y])
+            Ok(self.tile_unchecked(x, y))
         }
     }
+    // allocates the containing chunk if it does not exist yet
     pub fn get_tile_mut(&mut self, x: usize, y: usize) -> Result<&mut Tile, String> {
         if x >= self.width || y >= self.height {
             Err(format!("The tile at ({}, {}) is out of bounds", x, y))
         } else {
-            Ok(&mut self.tiles[x][y])
+            let index = (y / CHUNK_SIZE) * self.chunks_wide + x / CHUNK_SIZE;
+            let chunk = self.chunks[index].get_or_insert_with(Chunk::new);
+            Ok(chunk.get_mut(x % CHUNK_SIZE, y % CHUNK_SIZE))
+        }
+    }
+    fn tile_unchecked(&self, x: usize, y: usize) -> &Tile {
+        match &self.chunks[(y / CHUNK_SIZE) * self.chunks_wide + x / CHUNK_SIZE] {
+            Some(c) => c.get(x % CHUNK_SIZE, y % CHUNK_SIZE),
+            None => &EMPTY_TILE,
+        }
+    }
+    pub fn get_wall(&self, x: usize, y: usize) -> Result<&Wall, String> {
+        if x >= self.width || y >= self.height {
+            Err(format!("The wall at ({}, {}) is out of bounds", x, y))
+        } else {
+            Ok(self.wall_unchecked(x, y))
+        }
+    }
+    // allocates the containing chunk if it does not exist yet
+    pub fn get_wall_mut(&mut self, x: usize, y: usize) -> Result<&mut Wall, String> {
+        if x >= self.width || y >= self.height {
+            Err(format!("The wall at ({}, {}) is out of bounds", x, y))
+        } else {
+            let index = (y / CHUNK_SIZE) * self.chunks_wide + x / CHUNK_SIZE;
+            let chunk = self.chunks[index].get_or_insert_with(Chunk::new);
+            Ok(chunk.get_wall_mut(x % CHUNK_SIZE, y % CHUNK_SIZE))
+        }
+    }
+    fn wall_unchecked(&self, x: usize, y: usize) -> &Wall {
+        match &self.chunks[(y / CHUNK_SIZE) * self.chunks_wide + x / CHUNK_SIZE] {
+            Some(c) => c.get_wall(x % CHUNK_SIZE, y % CHUNK_SIZE),
+            None => &EMPTY_WALL,
         }
     }
-    pub fn get_neighbors_to(&self, x: usize, y: usize) -> Result<[Option<TileId>; 4], String> {
-        let mut neighbors: [Option<TileId>; 4] = [None; 4];
-        // values cannot be < 0 as they are of type usize
+    pub fn get_liquid(&self, x: usize, y: usize) -> Result<&Liquid, String> {
+        if x >= self.width || y >= self.height {
+            Err(format!("The liquid at ({}, {}) is out of bounds", x, y))
+        } else {
+            match &self.chunks[(y / CHUNK_SIZE) * self.chunks_wide + x / CHUNK_SIZE] {
+                Some(c) => Ok(c.get_liquid(x % CHUNK_SIZE, y % CHUNK_SIZE)),
+                None => Ok(&EMPTY_LIQUID),
+            }
+        }
+    }
+    // writes the liquid at (x, y) and wakes the liquid simulation around it
+    pub fn set_liquid(&mut self, x: usize, y: usize, liquid: Liquid) -> Result<(), String> {
+        if x >= self.width || y >= self.height {
+            return Err(format!("The liquid at ({}, {}) is out of bounds", x, y));
+        }
+        let index = (y / CHUNK_SIZE) * self.chunks_wide + x / CHUNK_SIZE;
+        if liquid.is_empty() && self.chunks[index].is_none() {
+            return Ok(());
+        }
+        let chunk = self.chunks[index].get_or_insert_with(Chunk::new);
+        *chunk.get_liquid_mut(x % CHUNK_SIZE, y % CHUNK_SIZE) = liquid;
+        self.wake_liquid(x, y);
+        Ok(())
+    }
+    // marks the chunks that (x, y) and its neighbors live in as having liquid that may move
+    pub fn wake_liquid(&mut self, x: usize, y: usize) {
+        for j in y.saturating_sub(1)..=(y + 1).min(self.height - 1) {
+            for i in x.saturating_sub(1)..=(x + 1).min(self.width - 1) {
+                let index = (j / CHUNK_SIZE) * self.chunks_wide + i / CHUNK_SIZE;
+                if self.chunks[index].is_some() {
+                    self.active_liquid_chunks.insert(index);
+                }
+            }
+        }
+    }
+    // hands the active chunks to the liquid simulation, bottom row of chunks first, and clears
+    // the set; chunks where something moves are woken again for the next step
+    pub fn take_active_liquid_chunks(&mut self) -> Vec<(usize, usize)> {
+        let active = std::mem::take(&mut self.active_liquid_chunks);
+        active.into_iter().rev().map(|i| (i % self.chunks_wide, i / self.chunks_wide)).collect()
+    }
+    // hands the cells edited through `set_tile` to the lighting and clears the list
+    pub fn take_light_changes(&mut self) -> Vec<(usize, usize)> {
+        std::mem::take(&mut self.light_changes)
+    }
+    // the cells [x0, x1) x [y0, y1) covered by a chunk, clamped to the world
+    pub fn chunk_cell_span(&self, cx: usize, cy: usize) -> (usize, usize, usize, usize) {
+        let (x0, y0) = (cx * CHUNK_SIZE, cy * CHUNK_SIZE);
+        (x0, y0, (x0 + CHUNK_SIZE).min(self.width), (y0 + CHUNK_SIZE).min(self.height))
+    }
+    // chunk coordinates, not tile coordinates
+    pub fn get_chunk(&self, cx: usize, cy: usize) -> Option<&Chunk> {
+        if cx >= self.chunks_wide || cy >= self.chunks_high {
+            None
+        } else {
+            self.chunks[cy * self.chunks_wide + cx].as_ref()
+        }
+    }
+    // frees every chunk that has gone back to being all `TileId::NOTHING` with no walls or liquid
+    pub fn compact(&mut self) {
+        for c in self.chunks.iter_mut() {
+            if c.as_ref().is_some_and(|chunk| chunk.is_empty()) {
+                *c = None;
+            }
+        }
+        let chunks = &self.chunks;
+        self.active_liquid_chunks.retain(|&i| chunks[i].is_some());
+    }
+    // yields (chunk x, chunk y, chunk) for every allocated chunk
+    pub fn chunks(&self) -> impl Iterator<Item=(usize, usize, &Chunk)> {
+        let chunks_wide = self.chunks_wide;
+        self.chunks.iter().enumerate().filter_map(move |(i, c)| {
+            c.as_ref().map(|chunk| (i % chunks_wide, i / chunks_wide, chunk))
+        })
+    }
+    // yields (chunk x, chunk y, chunk) for every allocated chunk overlapping the tile region
+    pub fn chunks_in(&self, left: usize, top: usize, width: usize, height: usize)
+                     -> impl Iterator<Item=(usize, usize, &Chunk)> {
+        let (cx0, cy0, cx1, cy1) = self.chunk_span(left, top, width, height);
+        (cy0..cy1).flat_map(move |cy| {
+            (cx0..cx1).filter_map(move |cx| self.get_chunk(cx, cy).map(|c| (cx, cy, c)))
+        })
+    }
+    // yields (x, y, tile) for every tile in the region, clamped to the world, row by row
+    pub fn tiles_in(&self, left: usize, top: usize, width: usize, height: usize)
+                    -> impl Iterator<Item=(usize, usize, &Tile)> {
+        let right = left.saturating_add(width).min(self.width);
+        let bottom = top.saturating_add(height).min(self.height);
+        (top..bottom).flat_map(move |y| {
+            (left..right).map(move |x| (x, y, self.tile_unchecked(x, y)))
+        })
+    }
+    // range of chunk coordinates [cx0, cx1) x [cy0, cy1) covering the tile region
+    fn chunk_span(&self, left: usize, top: usize, width: usize, height: usize) -> (usize, usize, usize, usize) {
+        let right = left.saturating_add(width).min(self.width);
+        let bottom = top.saturating_add(height).min(self.height);
+        if left >= right || top >= bottom {
+            return (0, 0, 0, 0);
+        }
+        (left / CHUNK_SIZE, top / CHUNK_SIZE,
+         right.div_ceil(CHUNK_SIZE), bottom.div_ceil(CHUNK_SIZE))
+    }
+    // looks up the eight neighbors of (x, y) in the order `Tile::set_neighbors` expects; `None`
+    // where the neighbor is outside the world
+    fn neighbors_with<T: Copy>(&self, x: usize, y: usize, get: impl Fn(usize, usize) -> T) -> [Option<T>; 8] {
+        const OFFSETS: [(i64, i64); 8] = [(-1, 0), (0, 1), (1, 0), (0, -1), (-1, -1), (1, -1), (-1, 1), (1, 1)];
+        let mut neighbors: [Option<T>; 8] = [None; 8];
+        for (n, (dx, dy)) in neighbors.iter_mut().zip(OFFSETS.iter()) {
+            let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+            if nx >= 0 && ny >= 0 && (nx as usize) < self.width && (ny as usize) < self.height {
+                *n = Some(get(nx as usize, ny as usize));
+            }
+        }
+        neighbors
+    }
+    pub fn get_neighbors_to(&self, x: usize, y: usize) -> Result<[Option<TileId>; 8], String> {
         if x >= self.width || y >= self.height {
             Err(format!("The tile at ({}, {}) is out of bounds", x, y))
         } else {
-            neighbors[0] = (if x == 0 {
-                None
-            } else {
-                Some(self.tiles[x - 1][y].get_id())
-            });
-            neighbors[1] = (if y + 1 >= self.height {
-                None
-            } else {
-                Some(self.tiles[x][y + 1].get_id())
-            });
-            neighbors[2] = (if x + 1 >= self.width {
-                None
-            } else {
-                Some(self.tiles[x + 1][y].get_id())
-            });
-            neighbors[3] = (if y == 0 {
-                None
-            } else {
-                Some(self.tiles[x][y - 1].get_id())
-            });
-            Ok(neighbors)
+            Ok(self.neighbors_with(x, y, |i, j| self.tile_unchecked(i, j).get_id()))
+        }
+    }
+    pub fn get_wall_neighbors_to(&self, x: usize, y: usize) -> Result<[Option<WallId>; 8], String> {
+        if x >= self.width || y >= self.height {
+            Err(format!("The wall at ({}, {}) is out of bounds", x, y))
+        } else {
+            Ok(self.neighbors_with(x, y, |i, j| self.wall_unchecked(i, j).get_id()))
+        }
+    }
+    // changes the tile at (x, y), picks its frame and refreshes the cached neighbors of it and the
+    // tiles around it, without touching the rest of the world; for bulk edits it is cheaper to
+    // write through `get_tile_mut` and call `update_cached_neighbors` once at the end
+    pub fn set_tile(&mut self, x: usize, y: usize, id: TileId) -> Result<(), String> {
+        if self.get_tile(x, y)?.get_id() == id {
+            return Ok(());
+        }
+        {
+            let t = self.get_tile_mut(x, y)?;
+            t.set_id(id);
+            t.set_frame(autotile::variant_at(x, y));
+            t.set_solid(id.info().solid);
+            t.set_shape(TileShape::Full);
+            t.set_part((0, 0));
+        }
+        self.refresh_neighbors_around(x, y);
+        // opening or closing a cell changes where nearby liquid can go
+        self.wake_liquid(x, y);
+        self.light_changes.push((x, y));
+        Ok(())
+    }
+    // diagonals matter for autotiling, so the whole 3x3 block around a cell is refreshed
+    fn refresh_neighbors_around(&mut self, x: usize, y: usize) {
+        for j in y.saturating_sub(1)..=y + 1 {
+            for i in x.saturating_sub(1)..=x + 1 {
+                self.refresh_neighbors(i, j);
+            }
         }
     }
+    // recomputes the cached neighbors of a single cell, both layers; does nothing outside the
+    // world or in chunks that were never allocated
+    fn refresh_neighbors(&mut self, x: usize, y: usize) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let neighbors = self.get_neighbors_to(x, y).unwrap();
+        let wall_neighbors = self.get_wall_neighbors_to(x, y).unwrap();
+        let index = (y / CHUNK_SIZE) * self.chunks_wide + x / CHUNK_SIZE;
+        if let Some(chunk) = &mut self.chunks[index] {
+            let (i, j) = (x % CHUNK_SIZE, y % CHUNK_SIZE);
+            let t = chunk.get_mut(i, j);
+            t.set_neighbors(neighbors);
+            chunk.get_wall_mut(i, j).set_neighbors(wall_neighbors);
+        }
+    }
+    // cells in unallocated chunks are empty and never drawn, so only allocated chunks need
+    // their caches refreshed
     pub fn update_cached_neighbors(&mut self) {
-        for i in 0usize..self.width {
-            for j in 0usize..self.height {
-                let neighbors = self.get_neighbors_to(i, j).unwrap();
-                let t = &mut self.tiles[i][j];
-                t.set_neighbors(neighbors);
+        for index in 0..self.chunks.len() {
+            if self.chunks[index].is_none() {
+                continue;
+            }
+            let base_x = (index % self.chunks_wide) * CHUNK_SIZE;
+            let base_y = (index / self.chunks_wide) * CHUNK_SIZE;
+            let mut neighbors = Vec::with_capacity(CHUNK_SIZE * CHUNK_SIZE);
+            for j in 0..CHUNK_SIZE {
+                for i in 0..CHUNK_SIZE {
+                    let (x, y) = (base_x + i, base_y + j);
+                    neighbors.push(self.get_neighbors_to(x, y).ok().zip(self.get_wall_neighbors_to(x, y).ok()));
+                }
+            }
+            let chunk = self.chunks[index].as_mut().unwrap();
+            for (k, n) in neighbors.into_iter().enumerate() {
+                if let Some((n, wn)) = n {
+                    let (i, j) = (k % CHUNK_SIZE, k / CHUNK_SIZE);
+                    chunk.get_mut(i, j).set_neighbors(n);
+                    chunk.get_wall_mut(i, j).set_neighbors(wn);
+                }
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,425 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::autotile;
+use crate::chunk::CHUNK_SIZE;
+use crate::liquid::{Liquid, LiquidKind, MAX_LIQUID};
+use crate::tile::{TileId, TileShape};
+use crate::tile_registry::TILE_REGISTRY;
+use crate::wall::{WallId, WALL_REGISTRY};
+use crate::world::World;
+
+// the smallest world the passes can fit their surface, dirt band and caves into
+pub const MIN_WIDTH: usize = CHUNK_SIZE;
+pub const MIN_HEIGHT: usize = CHUNK_SIZE;
+
+// data that passes hand down to the passes after them
+pub struct GenState {
+    // y of the topmost ground tile in each column
+    pub surface: Vec<usize>,
+}
+
+pub trait GenPass {
+    fn name(&self) -> &'static str;
+    fn apply(&self, world: &mut World, state: &mut GenState, rng: &mut StdRng) -> Result<(), String>;
+}
+
+pub struct WorldGen {
+    seed: u64,
+    passes: Vec<Box<dyn GenPass>>,
+}
+
+impl WorldGen {
+    // the standard set of passes, in the order they have to run
+    pub fn new(seed: u64) -> WorldGen {
+        let mut gen = WorldGen::empty(seed);
+        gen.add_pass(Box::new(SurfacePass));
+        gen.add_pass(Box::new(LayerPass));
+        gen.add_pass(Box::new(WallPass));
+        gen.add_pass(Box::new(CavePass));
+        gen.add_pass(Box::new(GrassPass));
+        gen.add_pass(Box::new(SlopePass));
+        gen.add_pass(Box::new(PoolPass));
+        gen
+    }
+    pub fn empty(seed: u64) -> WorldGen {
+        WorldGen {
+            seed,
+            passes: Vec::new(),
+        }
+    }
+    pub fn add_pass(&mut self, pass: Box<dyn GenPass>) {
+        self.passes.push(pass);
+    }
+    pub fn generate(&self, width: usize, height: usize) -> Result<World, String> {
+        if width < MIN_WIDTH || height < MIN_HEIGHT {
+            return Err(format!("A generated world must be at least {}x{} tiles, not {}x{}", MIN_WIDTH, MIN_HEIGHT,
+                               width, height));
+        }
+        let mut world = World::new(width, height);
+        let mut state = GenState {
+            surface: vec![height; width],
+        };
+        for (i, pass) in self.passes.iter().enumerate() {
+            // every pass gets its own stream so that changing one pass does not reshuffle the
+            // random numbers seen by all the passes after it
+            let mut rng = StdRng::seed_from_u64(hash(self.seed, i as u64));
+            pass.apply(&mut world, &mut state, &mut rng)
+                .map_err(|msg| format!("World generation pass {} failed: {}", pass.name(), msg))?;
+        }
+        world.update_cached_neighbors();
+        Ok(world)
+    }
+}
+
+// splitmix64-style mixing of a seed and a coordinate; used instead of `rng` wherever a value
+// has to depend only on its position
+fn hash(seed: u64, n: u64) -> u64 {
+    let mut z = seed ^ n.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// smoothly interpolated value noise in [-1, 1], with lattice points every `scale` units
+fn value_noise(seed: u64, x: f64, scale: f64) -> f64 {
+    let p = x / scale;
+    let i = p.floor();
+    let t = p - i;
+    let lattice = |n: f64| (hash(seed, n as i64 as u64) >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0;
+    let t = t * t * (3.0 - 2.0 * t);
+    lattice(i) * (1.0 - t) + lattice(i + 1.0) * t
+}
+
+// several octaves of `value_noise`, each at half the scale and amplitude of the last
+fn fractal_noise(seed: u64, x: f64, scale: f64, octaves: u32) -> f64 {
+    let mut total = 0.0;
+    let mut amplitude = 1.0;
+    let mut max = 0.0;
+    let mut scale = scale;
+    for octave in 0..octaves {
+        total += value_noise(hash(seed, u64::from(octave)), x, scale) * amplitude;
+        max += amplitude;
+        amplitude *= 0.5;
+        scale *= 0.5;
+    }
+    total / max
+}
+
+// passes refer to tiles by their registry names
+fn tile_named(name: &str) -> Result<TileId, String> {
+    TILE_REGISTRY.id(name).ok_or_else(|| format!("The tile registry has no tile named {}", name))
+}
+
+fn wall_named(name: &str) -> Result<WallId, String> {
+    WALL_REGISTRY.id(name).ok_or_else(|| format!("The wall registry has no wall named {}", name))
+}
+
+fn place(world: &mut World, x: usize, y: usize, id: TileId) {
+    if let Ok(t) = world.get_tile_mut(x, y) {
+        t.set_id(id);
+        t.set_frame(autotile::variant_at(x, y));
+        t.set_solid(id.info().solid);
+    }
+}
+
+// decides where the ground starts in every column
+pub struct SurfacePass;
+
+impl GenPass for SurfacePass {
+    fn name(&self) -> &'static str {
+        "surface"
+    }
+    fn apply(&self, world: &mut World, state: &mut GenState, rng: &mut StdRng) -> Result<(), String> {
+        let height = world.height() as f64;
+        let base = height * 0.3;
+        let amplitude = height * 0.08;
+        let seed = rng.gen::<u64>();
+        for x in 0..world.width() {
+            let offset = fractal_noise(seed, x as f64, 96.0, 4) * amplitude;
+            let y = (base + offset).round().max(1.0).min(height - 1.0);
+            state.surface[x] = y as usize;
+        }
+        Ok(())
+    }
+}
+
+// fills everything below the surface with a band of dirt on top of stone, and sprinkles clumps
+// of each material into the other
+pub struct LayerPass;
+
+impl GenPass for LayerPass {
+    fn name(&self) -> &'static str {
+        "layers"
+    }
+    fn apply(&self, world: &mut World, state: &mut GenState, rng: &mut StdRng) -> Result<(), String> {
+        let (dirt, stone) = (tile_named("dirt")?, tile_named("stone")?);
+        let (width, height) = (world.width(), world.height());
+        let seed = rng.gen::<u64>();
+        for x in 0..width {
+            let dirt_depth = 10.0 + fractal_noise(seed, x as f64, 48.0, 3) * 5.0;
+            let rock_top = state.surface[x] + dirt_depth.round() as usize;
+            for y in state.surface[x]..height {
+                place(world, x, y, if y < rock_top { dirt } else { stone });
+            }
+        }
+        let clumps = width * height / 2000;
+        for _ in 0..clumps {
+            let x = rng.gen_range(0, width);
+            let y = rng.gen_range(state.surface[x], height);
+            let id = match world.get_tile(x, y).map(|t| t.get_id()) {
+                Ok(t) if t == dirt => stone,
+                Ok(t) if t == stone => dirt,
+                _ => continue,
+            };
+            let radius = rng.gen_range(1, 4);
+            blob(world, x, y, radius, |t| t != TileId::NOTHING, id);
+        }
+        Ok(())
+    }
+}
+
+// puts a wall behind the ground, matching what the ground was made of before the caves are dug,
+// so that caves show a background instead of the sky
+pub struct WallPass;
+
+impl GenPass for WallPass {
+    fn name(&self) -> &'static str {
+        "walls"
+    }
+    fn apply(&self, world: &mut World, state: &mut GenState, _rng: &mut StdRng) -> Result<(), String> {
+        let (dirt, stone) = (tile_named("dirt")?, tile_named("stone")?);
+        let (dirt_wall, stone_wall) = (wall_named("dirt_wall")?, wall_named("stone_wall")?);
+        for x in 0..world.width() {
+            // leave the first few rows bare so that walls never peek out above the surface
+            for y in (state.surface[x] + 3)..world.height() {
+                let id = world.get_tile(x, y)?.get_id();
+                let wall = if id == dirt {
+                    dirt_wall
+                } else if id == stone {
+                    stone_wall
+                } else {
+                    continue;
+                };
+                let w = world.get_wall_mut(x, y)?;
+                w.set_id(wall);
+                w.set_frame(autotile::variant_at(x, y));
+            }
+        }
+        Ok(())
+    }
+}
+
+// replaces every tile within `radius` of (cx, cy) that passes `filter`
+fn blob(world: &mut World, cx: usize, cy: usize, radius: i64, filter: impl Fn(TileId) -> bool, id: TileId) {
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            if dx * dx + dy * dy > radius * radius {
+                continue;
+            }
+            let (x, y) = (cx as i64 + dx, cy as i64 + dy);
+            if x < 0 || y < 0 {
+                continue;
+            }
+            let (x, y) = (x as usize, y as usize);
+            let current = match world.get_tile(x, y) {
+                Ok(t) => t.get_id(),
+                Err(_) => continue,
+            };
+            if filter(current) {
+                place(world, x, y, id);
+            }
+        }
+    }
+}
+
+// digs tunnels with random walks that drift in one general direction
+pub struct CavePass;
+
+impl GenPass for CavePass {
+    fn name(&self) -> &'static str {
+        "caves"
+    }
+    fn apply(&self, world: &mut World, state: &mut GenState, rng: &mut StdRng) -> Result<(), String> {
+        let (width, height) = (world.width(), world.height());
+        let walks = (width * height / 20000).max(1);
+        for _ in 0..walks {
+            let start_x = rng.gen_range(0, width);
+            let top = (state.surface[start_x] + 8).min(height - 1);
+            let mut x = start_x as f64;
+            let mut y = rng.gen_range(top, height) as f64;
+            let mut angle: f64 = rng.gen_range(0.0, std::f64::consts::PI * 2.0);
+            let steps = rng.gen_range(40, 200);
+            for _ in 0..steps {
+                let radius = rng.gen_range(1, 4);
+                blob(world, x as usize, y as usize, radius, |t| t != TileId::NOTHING, TileId::NOTHING);
+                angle += rng.gen_range(-0.5, 0.5);
+                x += angle.cos() * 1.5;
+                y += angle.sin() * 1.5;
+                if x < 0.0 || y < 0.0 || x >= width as f64 || y >= height as f64 {
+                    break;
+                }
+            }
+        }
+        // give back any chunk that was dug out completely
+        world.compact();
+        Ok(())
+    }
+}
+
+// turns every dirt tile that touches open sky into grass; dirt that only borders a cave stays dirt
+pub struct GrassPass;
+
+impl GenPass for GrassPass {
+    fn name(&self) -> &'static str {
+        "grass"
+    }
+    fn apply(&self, world: &mut World, _state: &mut GenState, _rng: &mut StdRng) -> Result<(), String> {
+        let (dirt, grass) = (tile_named("dirt")?, tile_named("grass")?);
+        // y of the topmost tile in each column, after the caves have been dug; everything above it
+        // is sky
+        let mut skyline = vec![world.height(); world.width()];
+        for (x, top) in skyline.iter_mut().enumerate() {
+            for y in 0..world.height() {
+                if world.get_tile(x, y)?.get_id() != TileId::NOTHING {
+                    *top = y;
+                    break;
+                }
+            }
+        }
+        let sky = |x: i64, y: i64| x >= 0 && y >= 0 && (x as usize) < skyline.len() && (y as usize) < skyline[x as usize];
+        let mut exposed = Vec::new();
+        for (cx, cy, chunk) in world.chunks() {
+            for (lx, ly, t) in chunk.iter() {
+                if t.get_id() != dirt {
+                    continue;
+                }
+                let (x, y) = ((cx * CHUNK_SIZE + lx) as i64, (cy * CHUNK_SIZE + ly) as i64);
+                // only the four sides count; a diagonal gap is not enough to expose it
+                if sky(x, y - 1) || sky(x - 1, y) || sky(x + 1, y) || sky(x, y + 1) {
+                    exposed.push((x as usize, y as usize));
+                }
+            }
+        }
+        for (x, y) in exposed {
+            place(world, x, y, grass);
+        }
+        Ok(())
+    }
+}
+
+// rounds off the surface: a step of one tile becomes a slope, and a lone bump becomes a half block
+pub struct SlopePass;
+
+impl GenPass for SlopePass {
+    fn name(&self) -> &'static str {
+        "slopes"
+    }
+    fn apply(&self, world: &mut World, state: &mut GenState, _rng: &mut StdRng) -> Result<(), String> {
+        let width = world.width();
+        let mut shapes = Vec::new();
+        for x in 1..width.saturating_sub(1) {
+            let y = state.surface[x];
+            if y + 1 >= world.height() || !world.get_tile(x, y)?.get_solid() {
+                continue;
+            }
+            let open = |nx: usize| world.get_tile(nx, y).map(|t| !t.get_solid());
+            let (left_open, right_open) = (open(x - 1)?, open(x + 1)?);
+            let shape = match (left_open, right_open) {
+                (true, true) => TileShape::Half,
+                (true, false) => TileShape::SlopeUpRight,
+                (false, true) => TileShape::SlopeUpLeft,
+                (false, false) => continue,
+            };
+            shapes.push((x, y, shape));
+        }
+        for (x, y, shape) in shapes {
+            world.get_tile_mut(x, y)?.set_shape(shape);
+        }
+        Ok(())
+    }
+}
+
+// drops liquid onto cave floors: water near the top, lava deep down; the liquid simulation
+// spreads it out once the world is running
+pub struct PoolPass;
+
+impl GenPass for PoolPass {
+    fn name(&self) -> &'static str {
+        "pools"
+    }
+    fn apply(&self, world: &mut World, state: &mut GenState, rng: &mut StdRng) -> Result<(), String> {
+        let (width, height) = (world.width(), world.height());
+        // most tries land inside solid ground and are dropped
+        let tries = (width * height / 4000).max(1);
+        for _ in 0..tries {
+            let x = rng.gen_range(0, width);
+            let top = (state.surface[x] + 8).min(height - 1);
+            let mut y = rng.gen_range(top, height);
+            // only open cells count, and the pool sits on whatever floor is below them
+            if world.get_tile(x, y)?.get_solid() {
+                continue;
+            }
+            while y + 1 < height && !world.get_tile(x, y + 1)?.get_solid() {
+                y += 1;
+            }
+            let kind = if y > height * 3 / 4 { LiquidKind::Lava } else { LiquidKind::Water };
+            let depth = rng.gen_range(1, 4);
+            for cy in y.saturating_sub(depth - 1)..=y {
+                if !world.get_tile(x, cy)?.get_solid() {
+                    world.set_liquid(x, cy, Liquid::of(kind, MAX_LIQUID))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    use crate::save;
+    use super::*;
+
+    // a hash of everything a save file keeps of the world
+    fn world_hash(world: &World) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        save::encode_world(world).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_world() {
+        let first = world_hash(&WorldGen::new(7).generate(300, 200).unwrap());
+        let second = world_hash(&WorldGen::new(7).generate(300, 200).unwrap());
+        let other = world_hash(&WorldGen::new(8).generate(300, 200).unwrap());
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn worlds_too_small_for_the_passes_are_rejected() {
+        for &(width, height) in &[(0, 0), (300, 0), (0, 200), (MIN_WIDTH, MIN_HEIGHT - 1), (MIN_WIDTH - 1, MIN_HEIGHT)] {
+            assert!(WorldGen::new(7).generate(width, height).is_err());
+        }
+        assert!(WorldGen::new(7).generate(MIN_WIDTH, MIN_HEIGHT).is_ok());
+    }
+
+    #[test]
+    fn grass_only_grows_under_the_sky() {
+        let world = WorldGen::new(7).generate(300, 200).unwrap();
+        let grass = tile_named("grass").unwrap();
+        for x in 0..world.width() {
+            let top = (0..world.height()).find(|&y| world.get_tile(x, y).unwrap().get_id() != TileId::NOTHING);
+            for y in 0..world.height() {
+                if world.get_tile(x, y).unwrap().get_id() != grass {
+                    continue;
+                }
+                // grass is either the top of its column or borders a column whose top is lower
+                let open = |nx: usize| (0..=y).all(|ny| world.get_tile(nx, ny).unwrap().get_id() == TileId::NOTHING);
+                let beside = (x > 0 && open(x - 1)) || (x + 1 < world.width() && open(x + 1));
+                assert!(Some(y) == top || beside, "grass at ({}, {}) is not under the sky", x, y);
+            }
+        }
+    }
+}